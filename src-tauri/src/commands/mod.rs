@@ -1,52 +1,679 @@
-use tauri::{State, AppHandle};
+use tauri::{State, AppHandle, Emitter};
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use chrono::Utc;
 use uuid::Uuid;
 
-use crate::models::{DbConfig, QueryResult};
-use crate::state::AppState;
+use crate::error::AppError;
+use crate::models::{
+    DbConfig, ErrorPolicy, ExecutionLimits, HistoryEntry, HistoryFilter, HistoryStatus, ExplainOptions, PoolSettings, PoolStatus, QueryOptions, QueryParam, QueryPlan, QueryResult, QueryStreamEvent, QuerySummary, ScriptReport,
+    SshTunnelConfig, StatementDescription, StatementInfo, StatementStatus, TransactionStatus, VariableScope,
+};
+use crate::state::{AppState, ConnectionDetails, OpenCursor};
+use crate::tunnel::SshTunnel;
 use crate::db::postgres::PostgresDriver;
 use crate::db::DatabaseDriver;
+use crate::db::pgfiles;
+use crate::db::meta::{message_result, parse_meta_command, split_meta_commands, MetaCommand, ScriptPart};
+use crate::db::splitter::{classify_statements, split_statements, statement_at};
+use crate::db::variables::{substitute_variables, Substituted};
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn connect_db(
     state: State<'_, AppState>,
     config: DbConfig,
-) -> Result<String, String> {
+    pin_sessions: Option<bool>,
+    variables: Option<HashMap<String, String>>,
+    limits: Option<ExecutionLimits>,
+    saved_connection_id: Option<String>,
+    connection_name: Option<String>,
+    ssh_tunnel: Option<SshTunnelConfig>,
+    pool: Option<PoolSettings>,
+) -> Result<String, AppError> {
+    let mut config = config;
+    resolve_secret(&state, &mut config.password, &config.password_ref)?;
+    pgfiles::resolve(&mut config)?;
+
+    // The pool connects to the tunnel's local end, which forwards to the configured host
+    let tunnel = match ssh_tunnel {
+        Some(_) if config.host.starts_with('/') => {
+            return Err(AppError::connection("A Unix socket can't be reached through an SSH tunnel"));
+        }
+        Some(mut tunnel_config) => {
+            resolve_secret(&state, &mut tunnel_config.password, &tunnel_config.password_ref)?;
+            resolve_secret(&state, &mut tunnel_config.passphrase, &tunnel_config.passphrase_ref)?;
+            let tunnel = SshTunnel::open(&tunnel_config, &config.host, config.port).await?;
+            if let Err(e) = tunnel.route(&mut config) {
+                tunnel.close().await;
+                return Err(e);
+            }
+            Some(tunnel)
+        }
+        None => None,
+    };
+
     // For MVP, strictly Postgres
     let mut driver = PostgresDriver::new();
-    driver.connect(&config).await?;
+    driver.set_auto_pin_sessions(pin_sessions.unwrap_or(false));
+    driver.set_default_limits(limits.unwrap_or_default());
+    driver.set_pool_settings(pool.unwrap_or_default());
+    if let Err(e) = driver.connect(&config).await {
+        if let Some(tunnel) = tunnel {
+            tunnel.close().await;
+        }
+        return Err(e);
+    }
 
     let connection_id = Uuid::new_v4().to_string();
+    if let Some(tunnel) = tunnel {
+        state.registry.tunnels.lock()?.insert(connection_id.clone(), tunnel);
+    }
     
-    let mut registry = state.registry.connections.lock().map_err(|e| e.to_string())?;
+    state.variables.set(VariableScope::Connection, &connection_id, variables.unwrap_or_default())?;
+    state.registry.details.lock()?.insert(connection_id.clone(), ConnectionDetails {
+        saved_connection_id,
+        name: connection_name,
+        database: config.dbname.clone(),
+    });
+
+    let mut registry = state.registry.connections.lock()?;
     registry.insert(connection_id.clone(), Arc::new(driver));
 
     Ok(connection_id)
 }
 
+// Fills in a secret saved in the credential store when it wasn't passed in plaintext
+fn resolve_secret(state: &AppState, secret: &mut Option<String>, reference: &Option<String>) -> Result<(), AppError> {
+    if let (None, Some(reference)) = (&secret, reference) {
+        let stored = state
+            .credentials
+            .fetch(reference)?
+            .ok_or_else(|| AppError::not_found("A saved secret is missing from the credential store"))?;
+        *secret = Some(stored);
+    }
+    Ok(())
+}
+
+// Substitutes `:name` references from the connection's and tab's variables, recording any
+// `\set` lines against the tab
+fn expand_variables(
+    state: &AppState,
+    connection_id: &str,
+    session_id: Option<&str>,
+    query: &str,
+) -> Result<Substituted, AppError> {
+    let variables = state.variables.resolve(connection_id, session_id)?;
+    let substituted = substitute_variables(query, &variables)?;
+    if let Some(session_id) = session_id {
+        state.variables.apply(session_id, &substituted.changes)?;
+    }
+    Ok(substituted)
+}
+
+// Points a server error position at the text the user wrote rather than the expanded text
+fn original_error(expanded: &Substituted, query: &str, error: AppError) -> AppError {
+    error.map_position(|position| expanded.original_position(query, position))
+}
+
+fn original_spans(expanded: &Substituted, results: &mut [QueryResult]) {
+    for result in results {
+        if let Some(span) = result.span.as_mut() {
+            *span = expanded.original_span(span);
+        }
+    }
+}
+
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn run_query(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    connection_id: String,
+    query: String,
+    params: Option<Vec<QueryParam>>,
+    query_id: Option<String>,
+    session_id: Option<String>,
+    page_size: Option<usize>,
+    limits: Option<ExecutionLimits>,
+) -> Result<Vec<QueryResult>, AppError> {
+    let started = Instant::now();
+    let options = QueryOptions {
+        query_id,
+        session_id,
+        params: params.unwrap_or_default(),
+        limits: limits.unwrap_or_default(),
+    };
+
+    let outcome = query_results(&state, &connection_id, &query, &options, page_size).await;
+    let stats = outcome.as_ref().map(|results| RunStats {
+        row_count: results.iter().map(|r| r.rows.len() as u64).sum(),
+        affected_rows: results.iter().map(|r| r.affected_rows).sum(),
+        cancelled: results.iter().any(|r| r.cancelled),
+    });
+    record_history(&app, &state, &connection_id, &query, &options, started, stats);
+    outcome
+}
+
+async fn query_results(
+    state: &AppState,
+    connection_id: &str,
+    query: &str,
+    options: &QueryOptions,
+    page_size: Option<usize>,
+) -> Result<Vec<QueryResult>, AppError> {
+    let driver = {
+        let registry = state.registry.connections.lock()?;
+        let driver = registry.get(connection_id).ok_or_else(|| AppError::not_found("Connection not found"))?;
+        driver.clone()
+    };
+    let expanded = expand_variables(state, connection_id, options.session_id.as_deref(), query)?;
+    match expanded_results(state, driver, connection_id, &expanded.sql, options, page_size).await {
+        Ok(mut results) => {
+            original_spans(&expanded, &mut results);
+            Ok(results)
+        }
+        Err(e) => Err(original_error(&expanded, query, e)),
+    }
+}
+
+async fn expanded_results(
+    state: &AppState,
+    driver: Arc<dyn DatabaseDriver>,
+    connection_id: &str,
+    query: &str,
+    options: &QueryOptions,
+    page_size: Option<usize>,
+) -> Result<Vec<QueryResult>, AppError> {
+    let options = options.clone();
+    let parts = split_meta_commands(query);
+    if parts.iter().any(|part| matches!(part, ScriptPart::Meta(_))) {
+        let results = run_with_meta_commands(state, driver.as_ref(), query, parts, &options).await?;
+        return Ok(with_display_settings(state, &options, results));
+    }
+
+    // Paged mode reads through a server-side cursor; statements that can't be paged run in full.
+    // A pinned session runs in full too, so the query sees the tab's own transaction.
+    // With no room left for a cursor, paged queries run in full as well.
+    let pinned = options.session_id.as_deref().is_some_and(|id| driver.has_session(id));
+    let cursor_limit = cursor_limit(driver.as_ref())?;
+    if let (Some(page_size), false, true) = (page_size, pinned, cursor_limit > 0) {
+        // Frees a connection for the new cursor when the connection already has its share open
+        close_cursors(state.cursors.take_over_limit(connection_id, cursor_limit)?).await;
+
+        if let Some(mut cursor) = driver.open_cursor(query, &options).await? {
+            let mut result = cursor.fetch_page(page_size).await?;
+
+            if result.has_more {
+                let result_id = Uuid::new_v4().to_string();
+                let mut cursors = state.cursors.cursors.lock()?;
+                cursors.insert(result_id.clone(), OpenCursor {
+                    connection_id: connection_id.to_string(),
+                    session_id: options.session_id.clone(),
+                    cursor: Arc::new(tokio::sync::Mutex::new(cursor)),
+                    last_used: Instant::now(),
+                });
+                result.result_id = Some(result_id);
+            } else {
+                cursor.close().await?;
+            }
+
+            return Ok(with_display_settings(state, &options, vec![result]));
+        }
+    }
+
+    let results = driver.query(query, &options).await?;
+    Ok(with_display_settings(state, &options, results))
+}
+
+// Rows returned, rows affected and whether the run was cut short, for the history entry
+struct RunStats {
+    row_count: u64,
+    affected_rows: u64,
+    cancelled: bool,
+}
+
+// Appends the run to the query history. Failing to write history never fails the query.
+fn record_history(
+    app: &AppHandle,
+    state: &AppState,
+    connection_id: &str,
+    sql: &str,
+    options: &QueryOptions,
+    started: Instant,
+    outcome: Result<RunStats, &AppError>,
+) {
+    let details = state
+        .registry
+        .details
+        .lock()
+        .ok()
+        .and_then(|details| details.get(connection_id).cloned());
+
+    let (status, stats, error) = match outcome {
+        Ok(stats) if stats.cancelled => (HistoryStatus::Cancelled, Some(stats), None),
+        Ok(stats) => (HistoryStatus::Succeeded, Some(stats), None),
+        Err(e) => (HistoryStatus::Failed, None, Some(e.to_string())),
+    };
+
+    let entry = HistoryEntry {
+        id: Uuid::new_v4().to_string(),
+        sql: sql.to_string(),
+        connection_id: connection_id.to_string(),
+        saved_connection_id: details.as_ref().and_then(|d| d.saved_connection_id.clone()),
+        connection_name: details.as_ref().and_then(|d| d.name.clone()),
+        database: details.map(|d| d.database),
+        session_id: options.session_id.clone(),
+        executed_at: Utc::now().to_rfc3339(),
+        duration_ms: started.elapsed().as_millis() as u64,
+        row_count: stats.as_ref().map_or(0, |s| s.row_count),
+        affected_rows: stats.as_ref().map_or(0, |s| s.affected_rows),
+        status,
+        error,
+    };
+    let _ = storage::append_history(app, &entry);
+}
+
+// Runs a script statement by statement and reports how each one went
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn run_script(
+    app: AppHandle,
     state: State<'_, AppState>,
     connection_id: String,
     query: String,
-) -> Result<QueryResult, String> {
+    on_error: Option<ErrorPolicy>,
+    query_id: Option<String>,
+    session_id: Option<String>,
+    limits: Option<ExecutionLimits>,
+) -> Result<ScriptReport, AppError> {
+    let started = Instant::now();
+    let options = QueryOptions { query_id, session_id, limits: limits.unwrap_or_default(), ..Default::default() };
+
+    let outcome = script_report(&state, &connection_id, &query, &options, on_error.unwrap_or_default()).await;
+    // A script that stopped on an error, or failed to commit, is recorded as failed
+    let stats = match &outcome {
+        Ok(report) => match report.error.as_ref().or_else(|| report.statements.iter().find_map(|s| s.error.as_ref())) {
+            Some(e) => Err(e),
+            None => Ok(RunStats {
+                row_count: report
+                    .statements
+                    .iter()
+                    .filter_map(|s| s.result.as_ref())
+                    .map(|r| r.rows.len() as u64)
+                    .sum(),
+                affected_rows: report.statements.iter().map(|s| s.affected_rows).sum(),
+                cancelled: report.statements.iter().any(|s| s.status == StatementStatus::Cancelled),
+            }),
+        },
+        Err(e) => Err(e),
+    };
+    record_history(&app, &state, &connection_id, &query, &options, started, stats);
+    outcome
+}
+
+async fn script_report(
+    state: &AppState,
+    connection_id: &str,
+    query: &str,
+    options: &QueryOptions,
+    policy: ErrorPolicy,
+) -> Result<ScriptReport, AppError> {
     let driver = {
-        let registry = state.registry.connections.lock().map_err(|e| e.to_string())?;
-        let driver = registry.get(&connection_id).ok_or("Connection not found")?;
+        let registry = state.registry.connections.lock()?;
+        let driver = registry.get(connection_id).ok_or_else(|| AppError::not_found("Connection not found"))?;
         driver.clone()
     };
 
-    driver.query(&query).await
+    let expanded = expand_variables(state, connection_id, options.session_id.as_deref(), query)?;
+    let local_meta = |command: &MetaCommand| local_meta_command(state, options, command);
+    let mut report = driver.run_script(&expanded.sql, options, policy, &local_meta).await?;
+    let display_expanded = options.session_id.as_deref().is_some_and(|id| state.display.is_expanded(id));
+
+    // Statement errors count from the start of their statement, in either text
+    for statement in &mut report.statements {
+        let span = expanded.original_span(&statement.span);
+        statement.error = statement.error.take().map(|error| {
+            error.map_position(|position| {
+                let position = expanded.sql[..statement.span.start].chars().count() + position;
+                expanded.original_position(query, position) - query[..span.start].chars().count()
+            })
+        });
+        if let Some(result) = statement.result.as_mut() {
+            result.span = Some(span.clone());
+            result.expanded = display_expanded;
+        }
+        statement.span = span;
+    }
+    Ok(report)
+}
+
+#[tauri::command]
+pub async fn split_script(query: String) -> Result<Vec<StatementInfo>, AppError> {
+    Ok(classify_statements(&query))
+}
+
+// Runs only the statement containing `cursor_offset`, a byte offset into `query`
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn run_statement_at(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    connection_id: String,
+    query: String,
+    cursor_offset: usize,
+    params: Option<Vec<QueryParam>>,
+    query_id: Option<String>,
+    session_id: Option<String>,
+    page_size: Option<usize>,
+    limits: Option<ExecutionLimits>,
+) -> Result<Vec<QueryResult>, AppError> {
+    let spans = split_statements(&query);
+    let statement = statement_at(&spans, cursor_offset)
+        .map(|index| spans[index].clone())
+        .ok_or_else(|| AppError::other("There is no statement to run"))?;
+
+    let text = query[statement.start..statement.end].to_string();
+    let preceding = query[..statement.start].chars().count();
+    let mut results = run_query(app, state, connection_id, text, params, query_id, session_id, page_size, limits)
+        .await
+        .map_err(|e| e.map_position(|position| position + preceding))?;
+
+    // Report spans and error positions against the whole script the editor holds
+    for result in &mut results {
+        if let Some(span) = result.span.as_mut() {
+            span.start += statement.start;
+            span.end += statement.start;
+        }
+    }
+    Ok(results)
+}
+
+// Runs a script that mixes SQL with psql backslash commands one part at a time, in order
+async fn run_with_meta_commands(
+    state: &AppState,
+    driver: &dyn DatabaseDriver,
+    query: &str,
+    parts: Vec<ScriptPart>,
+    options: &QueryOptions,
+) -> Result<Vec<QueryResult>, AppError> {
+    let mut results = Vec::new();
+
+    for part in parts {
+        match part {
+            ScriptPart::Sql(part_span) => {
+                let part = driver.query(&query[part_span.start..part_span.end], options).await.map_err(|e| {
+                    e.map_position(|position| position + query[..part_span.start].chars().count())
+                })?;
+                for mut result in part {
+                    // Spans come back relative to the part that ran
+                    if let Some(span) = result.span.as_mut() {
+                        span.start += part_span.start;
+                        span.end += part_span.start;
+                    }
+                    results.push(result);
+                }
+            }
+            ScriptPart::Meta(span) => {
+                let text = &query[span.start..span.end];
+                let command = parse_meta_command(text)?;
+                match local_meta_command(state, options, &command) {
+                    Some(message) => results.push(message_result(text, &message?, span)),
+                    None => {
+                        for mut result in driver.meta_command(&command, options).await? {
+                            result.span = Some(span.clone());
+                            results.push(result);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(results)
+}
+
+// Meta-commands that change the tab's display settings rather than query the server,
+// answered with the message psql prints
+fn local_meta_command(state: &AppState, options: &QueryOptions, command: &MetaCommand) -> Option<Result<String, AppError>> {
+    let MetaCommand::ExpandedDisplay(setting) = command else {
+        return None;
+    };
+    let expanded = options
+        .session_id
+        .as_deref()
+        .ok_or_else(|| AppError::other("\\x needs a tab to apply to"))
+        .and_then(|session_id| state.display.set_expanded(session_id, *setting));
+    Some(expanded.map(|expanded| {
+        let message = if expanded { "Expanded display is on." } else { "Expanded display is off." };
+        message.to_string()
+    }))
+}
+
+fn with_display_settings(state: &AppState, options: &QueryOptions, mut results: Vec<QueryResult>) -> Vec<QueryResult> {
+    let expanded = options.session_id.as_deref().is_some_and(|id| state.display.is_expanded(id));
+    for result in &mut results {
+        result.expanded = expanded;
+    }
+    results
+}
+
+#[tauri::command]
+pub async fn describe_query(
+    state: State<'_, AppState>,
+    connection_id: String,
+    query: String,
+    session_id: Option<String>,
+) -> Result<StatementDescription, AppError> {
+    let driver = {
+        let registry = state.registry.connections.lock()?;
+        let driver = registry.get(&connection_id).ok_or_else(|| AppError::not_found("Connection not found"))?;
+        driver.clone()
+    };
+
+    let options = QueryOptions { session_id, ..Default::default() };
+    driver.describe(&query, &options).await
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn explain_query(
+    state: State<'_, AppState>,
+    connection_id: String,
+    query: String,
+    params: Option<Vec<QueryParam>>,
+    explain: Option<ExplainOptions>,
+    query_id: Option<String>,
+    session_id: Option<String>,
+    limits: Option<ExecutionLimits>,
+) -> Result<QueryPlan, AppError> {
+    let driver = {
+        let registry = state.registry.connections.lock()?;
+        let driver = registry.get(&connection_id).ok_or_else(|| AppError::not_found("Connection not found"))?;
+        driver.clone()
+    };
+
+    let options = QueryOptions {
+        query_id,
+        session_id,
+        params: params.unwrap_or_default(),
+        limits: limits.unwrap_or_default(),
+    };
+    let expanded = expand_variables(&state, &connection_id, options.session_id.as_deref(), &query)?;
+    driver
+        .explain(&expanded.sql, &options, explain.unwrap_or_default())
+        .await
+        .map_err(|e| original_error(&expanded, &query, e))
+}
+
+const DEFAULT_PAGE_SIZE: usize = 1000;
+const MAX_CURSORS_PER_CONNECTION: usize = 4;
+pub const CURSOR_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[tauri::command]
+pub async fn fetch_next_page(
+    state: State<'_, AppState>,
+    result_id: String,
+    page_size: Option<usize>,
+) -> Result<QueryResult, AppError> {
+    let open = {
+        let cursors = state.cursors.cursors.lock()?;
+        let open = cursors.get(&result_id).ok_or_else(|| AppError::not_found("Result set not found"))?;
+        open.clone()
+    };
+
+    let mut cursor = open.cursor.lock().await;
+    let mut result = cursor.fetch_page(page_size.unwrap_or(DEFAULT_PAGE_SIZE)).await?;
+
+    if result.has_more {
+        state.cursors.touch(&result_id)?;
+        result.result_id = Some(result_id);
+    } else {
+        cursor.close().await?;
+        let mut cursors = state.cursors.cursors.lock()?;
+        cursors.remove(&result_id);
+    }
+
+    Ok(result)
+}
+
+#[tauri::command]
+pub async fn close_cursor(
+    state: State<'_, AppState>,
+    result_id: String,
+) -> Result<(), AppError> {
+    let open = {
+        let mut cursors = state.cursors.cursors.lock()?;
+        cursors.remove(&result_id)
+    };
+
+    if let Some(open) = open {
+        open.cursor.lock().await.close().await?;
+    }
+    Ok(())
+}
+
+// Cursors hold a pooled connection each, so they're kept to half the pool
+fn cursor_limit(driver: &dyn DatabaseDriver) -> Result<usize, AppError> {
+    let max_connections = driver.pool_status()?.max_connections as usize;
+    Ok((max_connections / 2).min(MAX_CURSORS_PER_CONNECTION))
+}
+
+// Ends each cursor's transaction, returning its connection to the pool. A fetch in progress
+// is waited for.
+async fn close_cursors(cursors: Vec<OpenCursor>) {
+    for open in cursors {
+        let _ = open.cursor.lock().await.close().await;
+    }
+}
+
+// Closes cursors nobody has fetched from for a while, so they stop holding back vacuum
+pub async fn expire_idle_cursors(state: &AppState) {
+    let expired = state.cursors.take_where(|open| {
+        open.last_used.elapsed() >= CURSOR_IDLE_TIMEOUT && open.cursor.try_lock().is_ok()
+    });
+    if let Ok(expired) = expired {
+        close_cursors(expired).await;
+    }
+}
+
+// Releases what the backend holds for a tab: its result cursors, pinned sessions, variables
+// and display settings
+#[tauri::command]
+pub async fn close_tab(
+    state: State<'_, AppState>,
+    tab_id: String,
+) -> Result<(), AppError> {
+    let cursors = state.cursors.take_where(|open| open.session_id.as_deref() == Some(tab_id.as_str()))?;
+    close_cursors(cursors).await;
+
+    let drivers: Vec<Arc<dyn DatabaseDriver>> = state.registry.connections.lock()?.values().cloned().collect();
+    for driver in drivers {
+        if driver.has_session(&tab_id) {
+            driver.release_session(&tab_id).await?;
+        }
+    }
+
+    state.variables.remove(VariableScope::Tab, &tab_id)?;
+    state.display.set_expanded(&tab_id, Some(false))?;
+    Ok(())
+}
+
+const QUERY_STREAM_EVENT: &str = "query-stream";
+const DEFAULT_STREAM_BATCH_SIZE: usize = 500;
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn run_query_stream(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    connection_id: String,
+    query_id: String,
+    session_id: Option<String>,
+    query: String,
+    params: Option<Vec<QueryParam>>,
+    batch_size: Option<usize>,
+    limits: Option<ExecutionLimits>,
+) -> Result<QuerySummary, AppError> {
+    let driver = {
+        let registry = state.registry.connections.lock()?;
+        let driver = registry.get(&connection_id).ok_or_else(|| AppError::not_found("Connection not found"))?;
+        driver.clone()
+    };
+
+    let emit = |event: QueryStreamEvent| {
+        let _ = app.emit(QUERY_STREAM_EVENT, event);
+    };
+
+    let options = QueryOptions {
+        query_id: Some(query_id),
+        session_id,
+        params: params.unwrap_or_default(),
+        limits: limits.unwrap_or_default(),
+    };
+    let local_meta = |command: &MetaCommand| local_meta_command(&state, &options, command);
+    let started = Instant::now();
+    let outcome = match expand_variables(&state, &connection_id, options.session_id.as_deref(), &query) {
+        Ok(expanded) => driver
+            .query_stream(&expanded.sql, &options, batch_size.unwrap_or(DEFAULT_STREAM_BATCH_SIZE), &emit, &local_meta)
+            .await
+            .map_err(|e| original_error(&expanded, &query, e)),
+        Err(e) => Err(e),
+    };
+
+    let stats = outcome.as_ref().map(|summary| RunStats {
+        row_count: summary.total_rows,
+        affected_rows: summary.affected_rows,
+        cancelled: summary.cancelled,
+    });
+    record_history(&app, &state, &connection_id, &query, &options, started, stats);
+    outcome
+}
+
+#[tauri::command]
+pub async fn cancel_query(
+    state: State<'_, AppState>,
+    connection_id: String,
+    query_id: String,
+) -> Result<bool, AppError> {
+    let driver = {
+        let registry = state.registry.connections.lock()?;
+        let driver = registry.get(&connection_id).ok_or_else(|| AppError::not_found("Connection not found"))?;
+        driver.clone()
+    };
+
+    driver.cancel_query(&query_id).await
 }
 
 #[tauri::command]
 pub async fn get_schemas(
     state: State<'_, AppState>,
     connection_id: String,
-) -> Result<Vec<String>, String> {
+) -> Result<Vec<String>, AppError> {
      let driver = {
-        let registry = state.registry.connections.lock().map_err(|e| e.to_string())?;
-        let driver = registry.get(&connection_id).ok_or("Connection not found")?;
+        let registry = state.registry.connections.lock()?;
+        let driver = registry.get(&connection_id).ok_or_else(|| AppError::not_found("Connection not found"))?;
         driver.clone()
     };
     driver.get_schemas().await
@@ -57,10 +684,10 @@ pub async fn get_tables(
     state: State<'_, AppState>,
     connection_id: String,
     schema: String,
-) -> Result<Vec<String>, String> {
+) -> Result<Vec<String>, AppError> {
      let driver = {
-        let registry = state.registry.connections.lock().map_err(|e| e.to_string())?;
-        let driver = registry.get(&connection_id).ok_or("Connection not found")?;
+        let registry = state.registry.connections.lock()?;
+        let driver = registry.get(&connection_id).ok_or_else(|| AppError::not_found("Connection not found"))?;
         driver.clone()
     };
     driver.get_tables(&schema).await
@@ -72,68 +699,251 @@ pub async fn get_columns(
     connection_id: String,
     schema: String,
     table: String,
-) -> Result<Vec<crate::models::ColumnDefinition>, String> {
+) -> Result<Vec<crate::models::ColumnDefinition>, AppError> {
      let driver = {
-        let registry = state.registry.connections.lock().map_err(|e| e.to_string())?;
-        let driver = registry.get(&connection_id).ok_or("Connection not found")?;
+        let registry = state.registry.connections.lock()?;
+        let driver = registry.get(&connection_id).ok_or_else(|| AppError::not_found("Connection not found"))?;
         driver.clone()
     };
     driver.get_columns(&schema, &table).await
 }
 
-use crate::models::{SavedConnection};
+use crate::db::connstring::{format_connection_string, parse_connection_string};
+use crate::models::{
+    ConfigProblem, ConnectionStringFormat, CredentialBackend, CredentialStatus, ImportedConnection, SavedConnection, SavedQuery,
+};
 use crate::storage;
 
 #[tauri::command]
 pub async fn save_connection(
     app: AppHandle,
+    state: State<'_, AppState>,
     connection: SavedConnection,
-) -> Result<(), String> {
-    storage::add_connection(&app, connection)
+) -> Result<(), AppError> {
+    storage::add_connection(&app, &state.credentials, connection)
 }
 
 #[tauri::command]
 pub async fn load_connections(
     app: AppHandle,
-) -> Result<Vec<SavedConnection>, String> {
-    storage::load_connections(&app)
+    state: State<'_, AppState>,
+) -> Result<Vec<SavedConnection>, AppError> {
+    storage::load_connections(&app, &state.credentials)
 }
 
 #[tauri::command]
 pub async fn delete_connection(
     app: AppHandle,
+    state: State<'_, AppState>,
     id: String,
-) -> Result<(), String> {
-    storage::delete_connection(&app, &id)
+) -> Result<(), AppError> {
+    storage::delete_connection(&app, &state.credentials, &id)
+}
+
+// A new, unsaved connection prefilled from a pasted URI or key/value connection string
+#[tauri::command]
+pub async fn import_connection_string(
+    input: String,
+) -> Result<ImportedConnection, AppError> {
+    let (config, warnings) = parse_connection_string(&input)?;
+    Ok(ImportedConnection {
+        connection: SavedConnection {
+            id: Uuid::new_v4().to_string(),
+            name: format!("{} ({})", config.dbname, config.host),
+            config,
+            variables: None,
+            limits: None,
+            ssh_tunnel: None,
+            pool: None,
+        },
+        warnings,
+    })
+}
+
+#[tauri::command]
+pub async fn export_connection_string(
+    state: State<'_, AppState>,
+    config: DbConfig,
+    format: ConnectionStringFormat,
+    include_password: Option<bool>,
+) -> Result<String, AppError> {
+    let include_password = include_password.unwrap_or(false);
+    let mut config = config;
+    if include_password && config.password.is_none() {
+        if let Some(reference) = &config.password_ref {
+            config.password = state.credentials.fetch(reference)?;
+        }
+    }
+    Ok(format_connection_string(&config, format, include_password))
+}
+
+#[tauri::command]
+pub async fn credential_status(
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<CredentialStatus, AppError> {
+    state.credentials.status(&storage::get_credentials_path(&app)?)
+}
+
+// Sets the master password on first use; afterwards it must match
+#[tauri::command]
+pub async fn unlock_credentials(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    master_password: String,
+) -> Result<(), AppError> {
+    state.credentials.unlock_file(&storage::get_credentials_path(&app)?, &master_password)
+}
+
+#[tauri::command]
+pub async fn lock_credentials(
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    state.credentials.lock_file()
+}
+
+// Kept in settings.json so the choice survives a restart
+#[tauri::command]
+pub async fn set_credential_backend(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    backend: CredentialBackend,
+) -> Result<(), AppError> {
+    let mut settings = storage::load_settings(&app)?;
+    settings.credential_backend = backend;
+    storage::save_settings(&app, settings)?;
+    state.credentials.set_preferred(backend)
+}
+
+#[tauri::command]
+pub async fn save_snippet(
+    app: AppHandle,
+    snippet: SavedQuery,
+) -> Result<SavedQuery, AppError> {
+    storage::add_snippet(&app, snippet)
+}
+
+#[tauri::command]
+pub async fn load_snippets(
+    app: AppHandle,
+) -> Result<Vec<SavedQuery>, AppError> {
+    storage::load_snippets(&app)
+}
+
+#[tauri::command]
+pub async fn delete_snippet(
+    app: AppHandle,
+    id: String,
+) -> Result<(), AppError> {
+    storage::delete_snippet(&app, &id)
+}
+
+#[tauri::command]
+pub async fn search_snippets(
+    app: AppHandle,
+    text: String,
+    folder: Option<String>,
+    tag: Option<String>,
+) -> Result<Vec<SavedQuery>, AppError> {
+    storage::search_snippets(&app, &text, folder.as_deref(), tag.as_deref())
 }
 
 #[tauri::command]
 pub async fn disconnect_db(
     state: State<'_, AppState>,
     connection_id: String,
-) -> Result<(), String> {
-    let mut registry = state.registry.connections.lock().map_err(|e| e.to_string())?;
-    registry.remove(&connection_id);
+) -> Result<(), AppError> {
+    let cursors = state.cursors.take_where(|open| open.connection_id == connection_id)?;
+    close_cursors(cursors).await;
+    state.variables.remove(VariableScope::Connection, &connection_id)?;
+    state.registry.details.lock()?.remove(&connection_id);
+
+    state.registry.connections.lock()?.remove(&connection_id);
+
+    let tunnel = state.registry.tunnels.lock()?.remove(&connection_id);
+    if let Some(tunnel) = tunnel {
+        tunnel.close().await;
+    }
     Ok(())
 }
 
-use crate::storage::Session;
+#[tauri::command]
+pub async fn search_history(
+    app: AppHandle,
+    filter: Option<HistoryFilter>,
+) -> Result<Vec<HistoryEntry>, AppError> {
+    storage::search_history(&app, &filter.unwrap_or_default())
+}
+
+#[tauri::command]
+pub async fn delete_history_entry(
+    app: AppHandle,
+    id: String,
+) -> Result<(), AppError> {
+    storage::delete_history_entry(&app, &id)
+}
+
+#[tauri::command]
+pub async fn clear_history(
+    app: AppHandle,
+) -> Result<(), AppError> {
+    storage::clear_history(&app)
+}
+
+const HISTORY_TAB_TITLE_LEN: usize = 40;
+
+// A new tab holding the entry's SQL, on the same connection if it is still open
+#[tauri::command]
+pub async fn reopen_history_entry(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    id: String,
+) -> Result<TabState, AppError> {
+    let entry = storage::get_history_entry(&app, &id)?;
+    let connected = state.registry.connections.lock()?.contains_key(&entry.connection_id);
+
+    let first_line = entry.sql.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or("");
+    let mut title: String = first_line.chars().take(HISTORY_TAB_TITLE_LEN).collect();
+    if first_line.chars().count() > HISTORY_TAB_TITLE_LEN {
+        title.push('…');
+    }
+
+    Ok(TabState {
+        id: Uuid::new_v4().to_string(),
+        title,
+        sql: entry.sql,
+        connection_id: connected.then_some(entry.connection_id),
+        saved_connection_id: entry.saved_connection_id,
+        db_name: entry.database,
+        variables: None,
+    })
+}
+
+use crate::storage::{Session, TabState};
 
 #[tauri::command]
 pub async fn save_session(
     app: AppHandle,
     session: Session,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     storage::save_session(&app, session)
 }
 
 #[tauri::command]
 pub async fn load_session(
     app: AppHandle,
-) -> Result<Session, String> {
+) -> Result<Session, AppError> {
     storage::load_session(&app)
 }
 
+// Corrupted config files found while loading, each reported once
+#[tauri::command]
+pub async fn take_config_problems(
+    state: State<'_, AppState>,
+) -> Result<Vec<ConfigProblem>, AppError> {
+    Ok(std::mem::take(&mut *state.config_problems.lock()?))
+}
+
 #[tauri::command]
 pub async fn update_cell(
     state: State<'_, AppState>,
@@ -144,10 +954,10 @@ pub async fn update_cell(
     col_type: Option<String>,
     new_value: Option<String>,
     row_identifiers: Vec<(String, Option<String>, String)>
-) -> Result<u64, String> {
+) -> Result<u64, AppError> {
     let driver = {
-        let registry = state.registry.connections.lock().map_err(|e| e.to_string())?;
-        let driver = registry.get(&connection_id).ok_or("Connection not found")?;
+        let registry = state.registry.connections.lock()?;
+        let driver = registry.get(&connection_id).ok_or_else(|| AppError::not_found("Connection not found"))?;
         driver.clone()
     };
     driver.update_cell(&schema, &table, &column, col_type, new_value, row_identifiers).await
@@ -156,7 +966,140 @@ pub async fn update_cell(
 #[tauri::command]
 pub async fn update_connections_list(
     app: AppHandle,
+    state: State<'_, AppState>,
     connections: Vec<SavedConnection>,
-) -> Result<(), String> {
-    storage::save_connections(&app, &connections)
+) -> Result<(), AppError> {
+    storage::save_connections(&app, &state.credentials, &connections)
+}
+
+#[tauri::command]
+pub async fn begin_transaction(
+    state: State<'_, AppState>,
+    connection_id: String,
+    session_id: String,
+    isolation_level: Option<String>,
+    read_only: Option<bool>,
+) -> Result<TransactionStatus, AppError> {
+    let driver = {
+        let registry = state.registry.connections.lock()?;
+        let driver = registry.get(&connection_id).ok_or_else(|| AppError::not_found("Connection not found"))?;
+        driver.clone()
+    };
+    driver.begin_transaction(&session_id, isolation_level, read_only.unwrap_or(false)).await
+}
+
+#[tauri::command]
+pub async fn commit(
+    state: State<'_, AppState>,
+    connection_id: String,
+    session_id: String,
+) -> Result<TransactionStatus, AppError> {
+    let driver = {
+        let registry = state.registry.connections.lock()?;
+        let driver = registry.get(&connection_id).ok_or_else(|| AppError::not_found("Connection not found"))?;
+        driver.clone()
+    };
+    driver.commit(&session_id).await
+}
+
+#[tauri::command]
+pub async fn rollback(
+    state: State<'_, AppState>,
+    connection_id: String,
+    session_id: String,
+) -> Result<TransactionStatus, AppError> {
+    let driver = {
+        let registry = state.registry.connections.lock()?;
+        let driver = registry.get(&connection_id).ok_or_else(|| AppError::not_found("Connection not found"))?;
+        driver.clone()
+    };
+    driver.rollback(&session_id).await
+}
+
+#[tauri::command]
+pub async fn transaction_status(
+    state: State<'_, AppState>,
+    connection_id: String,
+    session_id: String,
+) -> Result<TransactionStatus, AppError> {
+    let driver = {
+        let registry = state.registry.connections.lock()?;
+        let driver = registry.get(&connection_id).ok_or_else(|| AppError::not_found("Connection not found"))?;
+        driver.clone()
+    };
+    driver.transaction_status(&session_id).await
+}
+
+#[tauri::command]
+pub async fn pool_status(
+    state: State<'_, AppState>,
+    connection_id: String,
+) -> Result<PoolStatus, AppError> {
+    let driver = {
+        let registry = state.registry.connections.lock()?;
+        let driver = registry.get(&connection_id).ok_or_else(|| AppError::not_found("Connection not found"))?;
+        driver.clone()
+    };
+    driver.pool_status()
+}
+
+#[tauri::command]
+pub async fn pin_session(
+    state: State<'_, AppState>,
+    connection_id: String,
+    session_id: String,
+) -> Result<(), AppError> {
+    let driver = {
+        let registry = state.registry.connections.lock()?;
+        let driver = registry.get(&connection_id).ok_or_else(|| AppError::not_found("Connection not found"))?;
+        driver.clone()
+    };
+    driver.pin_session(&session_id).await
+}
+
+#[tauri::command]
+pub async fn reset_session(
+    state: State<'_, AppState>,
+    connection_id: String,
+    session_id: String,
+) -> Result<(), AppError> {
+    let driver = {
+        let registry = state.registry.connections.lock()?;
+        let driver = registry.get(&connection_id).ok_or_else(|| AppError::not_found("Connection not found"))?;
+        driver.clone()
+    };
+    driver.reset_session(&session_id).await
+}
+
+#[tauri::command]
+pub async fn release_session(
+    state: State<'_, AppState>,
+    connection_id: String,
+    session_id: String,
+) -> Result<(), AppError> {
+    let driver = {
+        let registry = state.registry.connections.lock()?;
+        let driver = registry.get(&connection_id).ok_or_else(|| AppError::not_found("Connection not found"))?;
+        driver.clone()
+    };
+    driver.release_session(&session_id).await
+}
+
+#[tauri::command]
+pub async fn get_variables(
+    state: State<'_, AppState>,
+    scope: VariableScope,
+    scope_id: String,
+) -> Result<HashMap<String, String>, AppError> {
+    state.variables.get(scope, &scope_id)
+}
+
+#[tauri::command]
+pub async fn set_variables(
+    state: State<'_, AppState>,
+    scope: VariableScope,
+    scope_id: String,
+    variables: HashMap<String, String>,
+) -> Result<(), AppError> {
+    state.variables.set(scope, &scope_id, variables)
 }
@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::Argon2;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+use crate::models::{CredentialBackend, CredentialStatus};
+use crate::storage::{remove_backups, write_atomic};
+
+// Service name entries are filed under in the OS keyring
+const KEYRING_SERVICE: &str = "pgmac";
+const KEYRING_PREFIX: &str = "keyring:";
+const FILE_PREFIX: &str = "file:";
+
+const FILE_VERSION: u32 = 1;
+// Encrypted with the derived key so a wrong master password is caught on unlock
+const VERIFIER_PLAINTEXT: &[u8] = b"pgmac-credentials";
+
+// Secrets are kept out of connections.json; the JSON holds a reference such as
+// `keyring:connection:<id>` naming the backend and the key within it.
+pub struct CredentialManager {
+    preferred: Mutex<CredentialBackend>,
+    file: Mutex<Option<UnlockedFile>>,
+}
+
+// The encrypted file and its key, held only between unlock and lock
+struct UnlockedFile {
+    path: PathBuf,
+    key: [u8; 32],
+}
+
+#[derive(Serialize, Deserialize)]
+struct CredentialFile {
+    version: u32,
+    salt: String,
+    verifier: Sealed,
+    entries: HashMap<String, Sealed>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Sealed {
+    nonce: String,
+    ciphertext: String,
+}
+
+impl CredentialManager {
+    pub fn new() -> Self {
+        Self {
+            preferred: Mutex::new(CredentialBackend::Keyring),
+            file: Mutex::new(None),
+        }
+    }
+
+    pub fn set_preferred(&self, backend: CredentialBackend) -> Result<(), AppError> {
+        *self.preferred.lock()? = backend;
+        Ok(())
+    }
+
+    pub fn status(&self, file_path: &Path) -> Result<CredentialStatus, AppError> {
+        Ok(CredentialStatus {
+            preferred: *self.preferred.lock()?,
+            keyring_available: keyring_available(),
+            file_exists: file_path.exists(),
+            file_unlocked: self.file.lock()?.is_some(),
+        })
+    }
+
+    // Opens the encrypted file with the master password, creating it on first use
+    pub fn unlock_file(&self, path: &Path, master_password: &str) -> Result<(), AppError> {
+        let key = if path.exists() {
+            let file = read_file(path)?;
+            let key = derive_key(master_password, &decode(&file.salt)?)?;
+            open(&key, &file.verifier, "verifier")
+                .ok()
+                .filter(|plaintext| plaintext == VERIFIER_PLAINTEXT)
+                .ok_or_else(|| AppError::credentials("Wrong master password"))?;
+            key
+        } else {
+            let mut salt = [0u8; 16];
+            OsRng.fill_bytes(&mut salt);
+            let key = derive_key(master_password, &salt)?;
+            write_file(path, &CredentialFile {
+                version: FILE_VERSION,
+                salt: BASE64.encode(salt),
+                verifier: seal(&key, VERIFIER_PLAINTEXT, "verifier")?,
+                entries: HashMap::new(),
+            })?;
+            key
+        };
+
+        *self.file.lock()? = Some(UnlockedFile { path: path.to_path_buf(), key });
+        Ok(())
+    }
+
+    pub fn lock_file(&self) -> Result<(), AppError> {
+        *self.file.lock()? = None;
+        Ok(())
+    }
+
+    // Stores the secret in the preferred backend, falling back to the encrypted file when the
+    // keyring can't be reached. Returns the reference to keep in place of the secret.
+    pub fn store(&self, key: &str, secret: &str) -> Result<String, AppError> {
+        let keyring_preferred = *self.preferred.lock()? == CredentialBackend::Keyring;
+        if keyring_preferred {
+            match keyring_entry(key)?.set_password(secret) {
+                Ok(()) => return Ok(format!("{}{}", KEYRING_PREFIX, key)),
+                Err(e) if !keyring_unreachable(&e) => return Err(keyring_error(e)),
+                Err(_) => {}
+            }
+        }
+
+        if keyring_preferred && self.file.lock()?.is_none() {
+            return Err(AppError::credentials_locked(
+                "The OS keyring isn't available; unlock the encrypted credential file to store passwords",
+            ));
+        }
+        self.with_file(|file, unlocked| {
+            file.entries.insert(key.to_string(), seal(&unlocked.key, secret.as_bytes(), key)?);
+            Ok(true)
+        })?;
+        Ok(format!("{}{}", FILE_PREFIX, key))
+    }
+
+    // None when the backend has no entry for the reference
+    pub fn fetch(&self, reference: &str) -> Result<Option<String>, AppError> {
+        match parse_reference(reference)? {
+            (CredentialBackend::Keyring, key) => match keyring_entry(key)?.get_password() {
+                Ok(secret) => Ok(Some(secret)),
+                Err(keyring::Error::NoEntry) => Ok(None),
+                Err(e) => Err(keyring_error(e)),
+            },
+            (CredentialBackend::EncryptedFile, key) => {
+                let mut secret = None;
+                self.with_file(|file, unlocked| {
+                    if let Some(sealed) = file.entries.get(key) {
+                        let plaintext = open(&unlocked.key, sealed, key)?;
+                        secret = Some(String::from_utf8(plaintext).map_err(|e| AppError::credentials(e.to_string()))?);
+                    }
+                    Ok(false)
+                })?;
+                Ok(secret)
+            }
+        }
+    }
+
+    pub fn remove(&self, reference: &str) -> Result<(), AppError> {
+        match parse_reference(reference)? {
+            (CredentialBackend::Keyring, key) => match keyring_entry(key)?.delete_credential() {
+                Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+                Err(e) => Err(keyring_error(e)),
+            },
+            (CredentialBackend::EncryptedFile, key) => {
+                self.with_file(|file, _| Ok(file.entries.remove(key).is_some()))
+            }
+        }
+    }
+
+    // Runs `f` against the decrypted file contents, writing them back when it returns true
+    fn with_file(&self, f: impl FnOnce(&mut CredentialFile, &UnlockedFile) -> Result<bool, AppError>) -> Result<(), AppError> {
+        let guard = self.file.lock()?;
+        let unlocked = guard
+            .as_ref()
+            .ok_or_else(|| AppError::credentials_locked("Unlock the credential file with the master password first"))?;
+
+        let mut file = read_file(&unlocked.path)?;
+        if f(&mut file, unlocked)? {
+            write_file(&unlocked.path, &file)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_reference(reference: &str) -> Result<(CredentialBackend, &str), AppError> {
+    if let Some(key) = reference.strip_prefix(KEYRING_PREFIX) {
+        Ok((CredentialBackend::Keyring, key))
+    } else if let Some(key) = reference.strip_prefix(FILE_PREFIX) {
+        Ok((CredentialBackend::EncryptedFile, key))
+    } else {
+        Err(AppError::credentials(format!("Unknown credential reference \"{}\"", reference)))
+    }
+}
+
+fn keyring_entry(key: &str) -> Result<keyring::Entry, AppError> {
+    keyring::Entry::new(KEYRING_SERVICE, key).map_err(keyring_error)
+}
+
+// A lookup that finds nothing still proves the keyring answers
+fn keyring_available() -> bool {
+    match keyring_entry("pgmac:probe").map(|entry| entry.get_password()) {
+        Ok(Ok(_)) | Ok(Err(keyring::Error::NoEntry)) => true,
+        Ok(Err(_)) | Err(_) => false,
+    }
+}
+
+// No Secret Service running, a locked keychain and the like, as opposed to a bad entry
+fn keyring_unreachable(err: &keyring::Error) -> bool {
+    matches!(err, keyring::Error::PlatformFailure(_) | keyring::Error::NoStorageAccess(_))
+}
+
+fn keyring_error(err: keyring::Error) -> AppError {
+    AppError::credentials(format!("OS keyring: {}", err))
+}
+
+fn derive_key(master_password: &str, salt: &[u8]) -> Result<[u8; 32], AppError> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(master_password.as_bytes(), salt, &mut key)
+        .map_err(|e| AppError::credentials(e.to_string()))?;
+    Ok(key)
+}
+
+// The entry key is bound in as associated data so ciphertexts can't be swapped between entries
+fn seal(key: &[u8; 32], plaintext: &[u8], aad: &str) -> Result<Sealed, AppError> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: plaintext, aad: aad.as_bytes() })
+        .map_err(|_| AppError::credentials("Encrypting the credential failed"))?;
+    Ok(Sealed {
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+    })
+}
+
+fn open(key: &[u8; 32], sealed: &Sealed, aad: &str) -> Result<Vec<u8>, AppError> {
+    let nonce = decode(&sealed.nonce)?;
+    if nonce.len() != 12 {
+        return Err(AppError::credentials("The credential file is corrupted"));
+    }
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    cipher
+        .decrypt(Nonce::from_slice(&nonce), Payload { msg: &decode(&sealed.ciphertext)?, aad: aad.as_bytes() })
+        .map_err(|_| AppError::credentials("Decrypting the credential failed"))
+}
+
+fn decode(value: &str) -> Result<Vec<u8>, AppError> {
+    BASE64
+        .decode(value)
+        .map_err(|_| AppError::credentials("The credential file is corrupted"))
+}
+
+fn read_file(path: &Path) -> Result<CredentialFile, AppError> {
+    let file: CredentialFile = serde_json::from_str(&fs::read_to_string(path)?)?;
+    if file.version != FILE_VERSION {
+        return Err(AppError::credentials(format!("Unsupported credential file version {}", file.version)));
+    }
+    Ok(file)
+}
+
+// Without backups, so removed or changed secrets don't linger in older copies
+fn write_file(path: &Path, file: &CredentialFile) -> Result<(), AppError> {
+    write_atomic(path, &serde_json::to_string_pretty(file)?)?;
+    remove_backups(path)
+}
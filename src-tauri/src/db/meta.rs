@@ -0,0 +1,144 @@
+use chrono::Utc;
+
+use crate::db::splitter::{skip_comment, skip_literal};
+use crate::error::AppError;
+use crate::models::{QueryResult, ServerNotice, StatementSpan};
+
+// psql backslash commands answered by the app instead of the server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetaCommand {
+    // `\d` lists relations; `\d pattern` describes each matching one
+    Describe { pattern: Option<String> },
+    ListTables { pattern: Option<String> },
+    ListFunctions { pattern: Option<String> },
+    ListSchemas { pattern: Option<String> },
+    ListDatabases { pattern: Option<String> },
+    // `\x` toggles, `\x on` / `\x off` set it explicitly
+    ExpandedDisplay(Option<bool>),
+}
+
+pub enum ScriptPart {
+    Sql(StatementSpan),
+    // Span covers the backslash through the end of the line
+    Meta(StatementSpan),
+}
+
+// Cuts a script into SQL runs and backslash-command lines, in order. A backslash outside
+// quoted text and comments starts a command that runs to the end of the line, as in psql.
+pub fn split_meta_commands(sql: &str) -> Vec<ScriptPart> {
+    let bytes = sql.as_bytes();
+    let mut parts = Vec::new();
+    let mut sql_start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if let Some(end) = skip_comment(bytes, i).or_else(|| skip_literal(bytes, i)) {
+            i = end;
+            continue;
+        }
+
+        match bytes[i] {
+            b'\\' => {
+                let end = bytes[i..].iter().position(|&b| b == b'\n').map_or(bytes.len(), |pos| i + pos);
+                push_sql(sql, sql_start, i, &mut parts);
+                parts.push(ScriptPart::Meta(StatementSpan { start: i, end: sql[..end].trim_end().len() }));
+                sql_start = end;
+                i = end;
+            }
+            _ => i += 1,
+        }
+    }
+
+    push_sql(sql, sql_start, bytes.len(), &mut parts);
+    parts
+}
+
+fn push_sql(sql: &str, start: usize, end: usize, parts: &mut Vec<ScriptPart>) {
+    let text = &sql[start..end];
+    if !text.trim().is_empty() {
+        let start = start + (text.len() - text.trim_start().len());
+        parts.push(ScriptPart::Sql(StatementSpan { start, end: start + text.trim().len() }));
+    }
+}
+
+pub fn parse_meta_command(text: &str) -> Result<MetaCommand, AppError> {
+    let text = text.trim().trim_start_matches('\\');
+    let (command, args) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let pattern = args.split_whitespace().next().map(str::to_string);
+
+    // The verbose `+` forms are accepted and return the same columns
+    match command.trim_end_matches('+') {
+        "d" => Ok(MetaCommand::Describe { pattern }),
+        "dt" => Ok(MetaCommand::ListTables { pattern }),
+        "df" => Ok(MetaCommand::ListFunctions { pattern }),
+        "dn" => Ok(MetaCommand::ListSchemas { pattern }),
+        "l" | "list" => Ok(MetaCommand::ListDatabases { pattern }),
+        "x" => match pattern.as_deref().map(str::to_lowercase).as_deref() {
+            None => Ok(MetaCommand::ExpandedDisplay(None)),
+            Some("on") => Ok(MetaCommand::ExpandedDisplay(Some(true))),
+            Some("off") => Ok(MetaCommand::ExpandedDisplay(Some(false))),
+            Some(other) => Err(AppError::other(format!("\\x: unrecognized value \"{}\": Boolean expected", other))),
+        },
+        _ => Err(AppError::other(format!("Invalid command \\{}", command))),
+    }
+}
+
+// Turns a psql name pattern into an optional schema regex and a name regex. `*` and `?` are
+// wildcards, unquoted text is folded to lower case and double quotes keep text as written.
+pub fn pattern_regexes(pattern: &str) -> (Option<String>, String) {
+    let mut parts = vec![String::new()];
+    let mut in_quotes = false;
+    let mut chars = pattern.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let part = parts.last_mut().expect("parts is never empty");
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                chars.next();
+                part.push('"');
+            }
+            '"' => in_quotes = !in_quotes,
+            '.' if !in_quotes => parts.push(String::new()),
+            '*' if !in_quotes => part.push_str(".*"),
+            '?' if !in_quotes => part.push('.'),
+            c if !in_quotes => push_regex_literal(part, c.to_lowercase()),
+            c => push_regex_literal(part, std::iter::once(c)),
+        }
+    }
+
+    let anchored = |p: &String| format!("^({})$", p);
+    let name = parts.pop().unwrap_or_default();
+    (parts.last().map(anchored), anchored(&name))
+}
+
+fn push_regex_literal(out: &mut String, chars: impl Iterator<Item = char>) {
+    for c in chars {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+}
+
+// A result that carries only a message, the way psql prints one for settings commands
+pub fn message_result(command: &str, message: &str, span: StatementSpan) -> QueryResult {
+    QueryResult {
+        columns: Vec::new(),
+        rows: Vec::new(),
+        affected_rows: 0,
+        query_type: "META".to_string(),
+        command_tag: command.to_string(),
+        duration_ms: 0,
+        span: Some(span),
+        notices: vec![ServerNotice {
+            severity: "INFO".to_string(),
+            message: message.to_string(),
+            timestamp: Utc::now().to_rfc3339(),
+        }],
+        cancelled: false,
+        result_id: None,
+        has_more: false,
+        expanded: false,
+        truncated: false,
+    }
+}
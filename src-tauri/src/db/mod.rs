@@ -1,16 +1,76 @@
 use async_trait::async_trait;
-use crate::models::{DbConfig, QueryResult, ColumnDefinition};
+use crate::db::meta::MetaCommand;
+use crate::error::AppError;
+use crate::models::{
+    DbConfig, ErrorPolicy, ExecutionLimits, ExplainOptions, QueryOptions, QueryPlan, QueryResult, QueryStreamEvent,
+    QuerySummary, ColumnDefinition, PoolStatus, ScriptReport, StatementDescription, TransactionStatus,
+};
 
+pub mod connstring;
+pub mod meta;
+pub mod notices;
+pub mod pgfiles;
+pub mod plan;
 pub mod postgres;
+pub mod splitter;
+pub mod variables;
+
+// Answers the meta-commands that change app settings rather than query the server, such as
+// \x, with the message to show. None leaves the command to the driver.
+pub type LocalMetaCommands<'a> = dyn Fn(&MetaCommand) -> Option<Result<String, AppError>> + Send + Sync + 'a;
 
 #[async_trait]
 pub trait DatabaseDriver: Send + Sync {
-    async fn connect(&mut self, config: &DbConfig) -> Result<(), String>;
-    async fn query(&self, sql: &str) -> Result<QueryResult, String>;
-    async fn get_schemas(&self) -> Result<Vec<String>, String>;
-    async fn get_tables(&self, schema: &str) -> Result<Vec<String>, String>;
-    async fn get_columns(&self, schema: &str, table: &str) -> Result<Vec<ColumnDefinition>, String>;
-    async fn ping(&self) -> Result<(), String>;
+    async fn connect(&mut self, config: &DbConfig) -> Result<(), AppError>;
+    // Limits applied to every query that doesn't set its own
+    fn set_default_limits(&mut self, limits: ExecutionLimits);
+    async fn query(&self, sql: &str, options: &QueryOptions) -> Result<Vec<QueryResult>, AppError>;
+    // Backslash commands in a stream or script run in turn with the SQL around them
+    async fn query_stream(
+        &self,
+        sql: &str,
+        options: &QueryOptions,
+        batch_size: usize,
+        on_event: &(dyn Fn(QueryStreamEvent) + Send + Sync),
+        local_meta: &LocalMetaCommands<'_>,
+    ) -> Result<QuerySummary, AppError>;
+    // Runs a script one statement at a time on one connection, applying `policy` to failures
+    async fn run_script(
+        &self,
+        sql: &str,
+        options: &QueryOptions,
+        policy: ErrorPolicy,
+        local_meta: &LocalMetaCommands<'_>,
+    ) -> Result<ScriptReport, AppError>;
+    // Prepares the statement without running it to report its parameter and column types
+    async fn describe(&self, sql: &str, options: &QueryOptions) -> Result<StatementDescription, AppError>;
+    // Plans a single statement, running it too with ANALYZE; writes are rolled back afterwards
+    async fn explain(&self, sql: &str, options: &QueryOptions, explain: ExplainOptions) -> Result<QueryPlan, AppError>;
+    // Answers catalog backslash commands such as \dt; display settings like \x are the caller's
+    async fn meta_command(&self, command: &MetaCommand, options: &QueryOptions) -> Result<Vec<QueryResult>, AppError>;
+    async fn cancel_query(&self, query_id: &str) -> Result<bool, AppError>;
+    // Explicit transactions pin a connection to the session (tab) until commit or rollback
+    async fn begin_transaction(
+        &self,
+        session_id: &str,
+        isolation_level: Option<String>,
+        read_only: bool,
+    ) -> Result<TransactionStatus, AppError>;
+    async fn commit(&self, session_id: &str) -> Result<TransactionStatus, AppError>;
+    async fn rollback(&self, session_id: &str) -> Result<TransactionStatus, AppError>;
+    async fn transaction_status(&self, session_id: &str) -> Result<TransactionStatus, AppError>;
+    // Keeps one connection for the session so SET, temp tables and prepared statements persist
+    async fn pin_session(&self, session_id: &str) -> Result<(), AppError>;
+    async fn reset_session(&self, session_id: &str) -> Result<(), AppError>;
+    async fn release_session(&self, session_id: &str) -> Result<(), AppError>;
+    fn has_session(&self, session_id: &str) -> bool;
+    fn pool_status(&self) -> Result<PoolStatus, AppError>;
+    // Returns None when the statement cannot be read through a cursor
+    async fn open_cursor(&self, sql: &str, options: &QueryOptions) -> Result<Option<Box<dyn QueryCursor>>, AppError>;
+    async fn get_schemas(&self) -> Result<Vec<String>, AppError>;
+    async fn get_tables(&self, schema: &str) -> Result<Vec<String>, AppError>;
+    async fn get_columns(&self, schema: &str, table: &str) -> Result<Vec<ColumnDefinition>, AppError>;
+    async fn ping(&self) -> Result<(), AppError>;
     async fn update_cell(
         &self, 
         schema: &str, 
@@ -19,5 +79,11 @@ pub trait DatabaseDriver: Send + Sync {
         col_type: Option<String>,
         new_value: Option<String>, 
         row_identifiers: Vec<(String, Option<String>, String)>
-    ) -> Result<u64, String>;
+    ) -> Result<u64, AppError>;
+}
+
+#[async_trait]
+pub trait QueryCursor: Send {
+    async fn fetch_page(&mut self, page_size: usize) -> Result<QueryResult, AppError>;
+    async fn close(&mut self) -> Result<(), AppError>;
 }
@@ -0,0 +1,98 @@
+use chrono::Utc;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::subscriber::Interest;
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+use crate::models::ServerNotice;
+
+// sqlx surfaces NoticeResponse messages only as tracing events under this target
+const NOTICE_TARGET: &str = "sqlx::postgres::notice";
+
+pub type NoticeBuffer = Arc<Mutex<Vec<ServerNotice>>>;
+
+tokio::task_local! {
+    static NOTICES: NoticeBuffer;
+}
+
+// Runs `fut` with notices raised while it is polled going into `buffer`.
+// sqlx reads from the socket inside the caller's future, so the events fire
+// within this task even though they carry no connection information.
+pub async fn collect_notices<F: Future>(buffer: NoticeBuffer, fut: F) -> F::Output {
+    NOTICES.scope(buffer, fut).await
+}
+
+pub fn drain_notices(buffer: &NoticeBuffer) -> Vec<ServerNotice> {
+    buffer
+        .lock()
+        .map(|mut notices| std::mem::take(&mut *notices))
+        .unwrap_or_default()
+}
+
+pub struct NoticeLayer;
+
+impl<S: Subscriber> Layer<S> for NoticeLayer {
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        if metadata.target() == NOTICE_TARGET {
+            Interest::always()
+        } else {
+            Interest::never()
+        }
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, _ctx: Context<'_, S>) -> bool {
+        metadata.target() == NOTICE_TARGET
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if event.metadata().target() != NOTICE_TARGET {
+            return;
+        }
+
+        let mut visitor = MessageVisitor(String::new());
+        event.record(&mut visitor);
+
+        let notice = ServerNotice {
+            severity: severity_for(*event.metadata().level()).to_string(),
+            message: visitor.0,
+            timestamp: Utc::now().to_rfc3339(),
+        };
+
+        // Notices outside a collect_notices scope (e.g. pool health checks) are dropped
+        let _ = NOTICES.try_with(|buffer| {
+            if let Ok(mut notices) = buffer.lock() {
+                notices.push(notice);
+            }
+        });
+    }
+}
+
+// sqlx maps the server severity onto a tracing level, so this reverses that mapping.
+// INFO and LOG share a level; both come back as INFO.
+fn severity_for(level: Level) -> &'static str {
+    match level {
+        Level::ERROR => "ERROR",
+        Level::WARN => "WARNING",
+        Level::INFO => "NOTICE",
+        Level::DEBUG => "DEBUG",
+        Level::TRACE => "INFO",
+    }
+}
+
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0 = value.to_string();
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" && self.0.is_empty() {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::db::connstring::{apply_defaults, apply_parameter, empty_config, DEFAULT_PORT};
+use crate::error::AppError;
+use crate::models::{DbConfig, SslMode};
+
+// Where libpq builds commonly look for the system-wide service file when PGSYSCONFDIR isn't
+// set (Debian/Ubuntu packages, source builds, Homebrew)
+const SYSTEM_SERVICE_FILES: &[&str] = &[
+    "/etc/postgresql-common/pg_service.conf",
+    "/etc/pg_service.conf",
+    "/usr/local/pgsql/etc/pg_service.conf",
+    "/opt/homebrew/etc/pg_service.conf",
+];
+
+// Socket directories libpq builds are compiled with (upstream default, Debian/Ubuntu, Red Hat)
+const DEFAULT_SOCKET_DIRS: &[&str] = &["/tmp", "/var/run/postgresql", "/run/postgresql"];
+
+// Completes a config the way libpq would from the same settings: the named service's
+// settings fill in what's left empty, then a missing password is looked up in the password
+// file. Run before any SSH tunnel replaces the host, since both files match on it.
+pub fn resolve(config: &mut DbConfig) -> Result<(), AppError> {
+    if let Some(service) = config.service.clone() {
+        apply_service(config, &service)?;
+    }
+    apply_defaults(config);
+
+    if config.password.is_none() && config.password_ref.is_none() {
+        config.password = lookup_password(config);
+    }
+    Ok(())
+}
+
+fn apply_service(config: &mut DbConfig, name: &str) -> Result<(), AppError> {
+    let (path, settings) = service_files()
+        .into_iter()
+        .find_map(|path| {
+            let settings = read_service(&path, name)?;
+            Some((path, settings))
+        })
+        .ok_or_else(|| AppError::connection(format!("Service \"{}\" isn't defined in any pg_service.conf", name)))?;
+
+    let mut service = empty_config();
+    for (key, value) in settings {
+        // Service files are shared with other clients, so settings only they can use are
+        // skipped quietly
+        apply_parameter(&mut service, &key, value).map_err(|e| {
+            AppError::connection(format!("Service \"{}\" in {}: {}", name, path.display(), e))
+        })?;
+    }
+
+    if config.host.is_empty() {
+        config.host = service.host;
+    }
+    if config.port == DEFAULT_PORT {
+        config.port = service.port;
+    }
+    if config.user.is_empty() {
+        config.user = service.user;
+    }
+    if config.dbname.is_empty() {
+        config.dbname = service.dbname;
+    }
+    if config.password.is_none() && config.password_ref.is_none() {
+        config.password = service.password;
+    }
+    if config.ssl_mode == SslMode::default() {
+        config.ssl_mode = service.ssl_mode;
+    }
+    config.ssl_root_cert = config.ssl_root_cert.take().or(service.ssl_root_cert);
+    config.ssl_client_cert = config.ssl_client_cert.take().or(service.ssl_client_cert);
+    config.ssl_client_key = config.ssl_client_key.take().or(service.ssl_client_key);
+    config.session.application_name = config.session.application_name.take().or(service.session.application_name);
+    for (key, value) in service.parameters {
+        config.parameters.entry(key).or_insert(value);
+    }
+    Ok(())
+}
+
+// The user's file comes first; the first file defining the service is the one used
+fn service_files() -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    match std::env::var_os("PGSERVICEFILE") {
+        Some(path) => files.push(PathBuf::from(path)),
+        None => files.extend(user_file(".pg_service.conf", ".pg_service.conf")),
+    }
+    match std::env::var_os("PGSYSCONFDIR") {
+        Some(dir) => files.push(Path::new(&dir).join("pg_service.conf")),
+        None => files.extend(SYSTEM_SERVICE_FILES.iter().map(PathBuf::from)),
+    }
+    files
+}
+
+// The settings of one `[name]` section, or None when the file or the section is missing
+fn read_service(path: &Path, name: &str) -> Option<Vec<(String, String)>> {
+    parse_service(&fs::read_to_string(path).ok()?, name)
+}
+
+fn parse_service(content: &str, name: &str) -> Option<Vec<(String, String)>> {
+    let mut settings = None;
+
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            if settings.is_some() {
+                break;
+            }
+            if section.trim() == name {
+                settings = Some(Vec::new());
+            }
+        } else if let (Some(settings), Some((key, value))) = (settings.as_mut(), line.split_once('=')) {
+            settings.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    settings
+}
+
+// Lines of `host:port:database:user:password`, where `*` matches anything and a backslash
+// escapes `:` or `\`. The first matching line wins.
+fn lookup_password(config: &DbConfig) -> Option<String> {
+    let path = match std::env::var_os("PGPASSFILE") {
+        Some(path) => PathBuf::from(path),
+        None => user_file(".pgpass", "pgpass.conf")?,
+    };
+    if !private_file(&path) {
+        return None;
+    }
+    find_password(&fs::read_to_string(&path).ok()?, config)
+}
+
+fn find_password(content: &str, config: &DbConfig) -> Option<String> {
+    let lines: Vec<Vec<String>> = content
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(split_pgpass_line)
+        .filter(|fields| fields.len() >= 5)
+        .collect();
+
+    // A socket directory is matched as written; the default one also matches "localhost"
+    let mut hosts = vec![config.host.as_str()];
+    if DEFAULT_SOCKET_DIRS.contains(&config.host.trim_end_matches('/')) {
+        hosts.push("localhost");
+    }
+    let port = config.port.to_string();
+
+    hosts.into_iter().find_map(|host| {
+        let wanted = [host, &port, &config.dbname, &config.user];
+        lines.iter().find_map(|fields| {
+            let matches = wanted
+                .iter()
+                .zip(fields)
+                .all(|(wanted, field)| field == "*" || field == wanted);
+            matches.then(|| fields[4..].join(":"))
+        })
+    })
+}
+
+fn split_pgpass_line(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => fields.last_mut().expect("fields is never empty").extend(chars.next()),
+            ':' => fields.push(String::new()),
+            c => fields.last_mut().expect("fields is never empty").push(c),
+        }
+    }
+    fields
+}
+
+// libpq ignores a password file that other users can read
+#[cfg(unix)]
+fn private_file(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o077 == 0)
+}
+
+#[cfg(not(unix))]
+fn private_file(path: &Path) -> bool {
+    path.is_file()
+}
+
+// `~/<unix_name>`, or `%APPDATA%\postgresql\<windows_name>` on Windows
+fn user_file(unix_name: &str, windows_name: &str) -> Option<PathBuf> {
+    if cfg!(windows) {
+        let appdata = std::env::var_os("APPDATA")?;
+        Some(Path::new(&appdata).join("postgresql").join(windows_name))
+    } else {
+        Some(dirs::home_dir()?.join(unix_name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(host: &str, port: u16, dbname: &str, user: &str) -> DbConfig {
+        DbConfig {
+            host: host.to_string(),
+            port,
+            dbname: dbname.to_string(),
+            user: user.to_string(),
+            ..empty_config()
+        }
+    }
+
+    #[test]
+    fn pgpass_unescapes_colons_and_backslashes() {
+        let content = r"db\:1:5432:app:alice:pa\:ss\\word";
+        assert_eq!(find_password(content, &config("db:1", 5432, "app", "alice")).as_deref(), Some(r"pa:ss\word"));
+        // The host field is compared unescaped
+        assert_eq!(find_password(content, &config(r"db\:1", 5432, "app", "alice")), None);
+        // Unescaped colons past the fourth field are part of the password
+        let content = "db:5432:app:alice:one:two";
+        assert_eq!(find_password(content, &config("db", 5432, "app", "alice")).as_deref(), Some("one:two"));
+    }
+
+    #[test]
+    fn pgpass_wildcards_and_first_match_wins() {
+        let content = "\
+# comment lines and blank lines are skipped
+db:5432:staging:*:staging-secret
+
+*:*:*:alice:alice-anywhere
+db:*:*:*:db-fallback
+db:5432:app:alice:too-late
+";
+        assert_eq!(find_password(content, &config("db", 5432, "staging", "bob")).as_deref(), Some("staging-secret"));
+        assert_eq!(find_password(content, &config("other", 6543, "app", "alice")).as_deref(), Some("alice-anywhere"));
+        assert_eq!(find_password(content, &config("db", 5432, "app", "alice")).as_deref(), Some("alice-anywhere"));
+        assert_eq!(find_password(content, &config("db", 6543, "app", "bob")).as_deref(), Some("db-fallback"));
+        assert_eq!(find_password(content, &config("other", 5432, "app", "bob")), None);
+        // A short line never matches
+        assert_eq!(find_password("db:5432:app", &config("db", 5432, "app", "")), None);
+    }
+
+    #[test]
+    fn pgpass_default_socket_dir_matches_localhost() {
+        let content = "localhost:5432:app:alice:local-secret";
+        assert_eq!(find_password(content, &config("/var/run/postgresql/", 5432, "app", "alice")).as_deref(), Some("local-secret"));
+        assert_eq!(find_password(content, &config("/home/alice/sockets", 5432, "app", "alice")), None);
+    }
+
+    #[test]
+    fn service_file_reads_one_section() {
+        let content = "\
+# shared settings aren't a section
+[reporting]
+host = reports.internal
+  port=6543
+
+# dbname=commented
+dbname=reports
+[ other ]
+host=elsewhere
+[reporting]
+user=ignored
+";
+        let settings = parse_service(content, "reporting").unwrap();
+        let settings: Vec<(&str, &str)> = settings.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        assert_eq!(settings, [("host", "reports.internal"), ("port", "6543"), ("dbname", "reports")]);
+
+        // Section names are trimmed
+        assert_eq!(parse_service(content, "other").unwrap(), [("host".to_string(), "elsewhere".to_string())]);
+        assert_eq!(parse_service(content, "missing"), None);
+        assert_eq!(parse_service("host=nowhere", "reporting"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pgpass_readable_by_others_is_ignored() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("pgpass-{}", uuid::Uuid::new_v4().simple()));
+        fs::write(&path, "*:*:*:*:secret\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        assert!(private_file(&path));
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        assert!(!private_file(&path));
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o604)).unwrap();
+        assert!(!private_file(&path));
+        fs::remove_file(&path).unwrap();
+        // Nor is a missing file or a directory
+        assert!(!private_file(&path));
+        assert!(!private_file(&std::env::temp_dir()));
+    }
+}
@@ -0,0 +1,271 @@
+use serde_json::Value;
+
+use crate::error::AppError;
+use crate::models::{PlanBuffers, PlanNode, QueryPlan};
+
+// Builds the typed plan from EXPLAIN (FORMAT JSON) output, which is a one-element array
+// holding the plan and its timings
+pub fn parse_plan(raw: Value, analyzed: bool, rolled_back: bool) -> Result<QueryPlan, AppError> {
+    let top = raw
+        .as_array()
+        .and_then(|items| items.first())
+        .ok_or_else(|| AppError::other("EXPLAIN returned an unexpected plan format"))?;
+    let root = top
+        .get("Plan")
+        .ok_or_else(|| AppError::other("EXPLAIN output has no plan"))?;
+
+    Ok(QueryPlan {
+        root: parse_node(root, 1.0),
+        planning_time_ms: number(top, "Planning Time"),
+        execution_time_ms: number(top, "Execution Time"),
+        analyzed,
+        rolled_back,
+        raw,
+    })
+}
+
+// `processes` is how many processes ran the node: below a Gather, the leader and each worker
+// run the whole subtree, and the loops they report add up across them.
+fn parse_node(node: &Value, processes: f64) -> PlanNode {
+    let child_processes = match number(node, "Workers Launched") {
+        Some(workers) if node.get("Single Copy").and_then(Value::as_bool) == Some(true) => workers.max(1.0),
+        Some(workers) => workers + 1.0,
+        None => processes,
+    };
+    let children: Vec<PlanNode> = node
+        .get("Plans")
+        .and_then(Value::as_array)
+        .map(|plans| plans.iter().map(|plan| parse_node(plan, child_processes)).collect())
+        .unwrap_or_default();
+
+    let actual_total_time_ms = number(node, "Actual Total Time");
+    let actual_loops = number(node, "Actual Loops");
+    let actual_rows = number(node, "Actual Rows");
+    let plan_rows = number(node, "Plan Rows").unwrap_or(0.0);
+
+    // Times are per-loop averages, so each node's total is time × loops, shared out between the
+    // processes running it in parallel. Timing overhead can still leave the children a little
+    // over their parent; clamp at 0. Rounded to the microseconds EXPLAIN reports so float noise
+    // doesn't show up.
+    let exclusive_time_ms = actual_total_time_ms.map(|time| {
+        let own = time * actual_loops.unwrap_or(1.0) / processes;
+        let in_children: f64 = children
+            .iter()
+            .filter_map(|child| Some(child.actual_total_time_ms? * child.actual_loops.unwrap_or(1.0) / child_processes))
+            .sum();
+        ((own - in_children).max(0.0) * 1000.0).round() / 1000.0
+    });
+
+    // Both sides are floored at one row so empty results don't divide by zero. A node that
+    // never ran (0 loops) has no rows to compare.
+    let row_estimate_ratio = actual_rows
+        .filter(|_| actual_loops != Some(0.0))
+        .map(|rows| rows.max(1.0) / plan_rows.max(1.0));
+
+    PlanNode {
+        node_type: text(node, "Node Type").unwrap_or_default(),
+        parent_relationship: text(node, "Parent Relationship"),
+        relation_name: text(node, "Relation Name"),
+        schema: text(node, "Schema"),
+        alias: text(node, "Alias"),
+        index_name: text(node, "Index Name"),
+        join_type: text(node, "Join Type"),
+        filter: text(node, "Filter"),
+        index_cond: text(node, "Index Cond"),
+        hash_cond: text(node, "Hash Cond"),
+        startup_cost: number(node, "Startup Cost").unwrap_or(0.0),
+        total_cost: number(node, "Total Cost").unwrap_or(0.0),
+        plan_rows,
+        plan_width: number(node, "Plan Width").unwrap_or(0.0) as u64,
+        actual_startup_time_ms: number(node, "Actual Startup Time"),
+        actual_total_time_ms,
+        actual_rows,
+        actual_loops,
+        rows_removed_by_filter: number(node, "Rows Removed by Filter"),
+        buffers: parse_buffers(node),
+        output: node
+            .get("Output")
+            .and_then(Value::as_array)
+            .map(|items| items.iter().filter_map(|item| item.as_str().map(str::to_string)).collect())
+            .unwrap_or_default(),
+        exclusive_time_ms,
+        row_estimate_ratio,
+        children,
+    }
+}
+
+// Buffer counters are flattened into the node, and only present with BUFFERS
+fn parse_buffers(node: &Value) -> Option<PlanBuffers> {
+    node.get("Shared Hit Blocks")?;
+    let count = |key: &str| number(node, key).unwrap_or(0.0) as u64;
+    Some(PlanBuffers {
+        shared_hit: count("Shared Hit Blocks"),
+        shared_read: count("Shared Read Blocks"),
+        shared_dirtied: count("Shared Dirtied Blocks"),
+        shared_written: count("Shared Written Blocks"),
+        local_hit: count("Local Hit Blocks"),
+        local_read: count("Local Read Blocks"),
+        temp_read: count("Temp Read Blocks"),
+        temp_written: count("Temp Written Blocks"),
+    })
+}
+
+fn text(node: &Value, key: &str) -> Option<String> {
+    node.get(key).and_then(Value::as_str).map(str::to_string)
+}
+
+fn number(node: &Value, key: &str) -> Option<f64> {
+    node.get(key).and_then(Value::as_f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Captured from Postgres 15 with hash and merge joins, memoize and parallel workers
+    // disabled, so the inner index scan runs once per outer row:
+    //   EXPLAIN (ANALYZE, BUFFERS, FORMAT JSON) SELECT c.region, count(*) FROM orders o
+    //   JOIN customers c ON c.id = o.customer_id WHERE o.id % 2 = 0 AND o.id % 3 = 0 GROUP BY c.region
+    const NESTED_LOOP: &str = include_str!("fixtures/explain_nested_loop.json");
+    //   EXPLAIN (ANALYZE, FORMAT JSON) SELECT * FROM orders WHERE total > 50 LIMIT 0
+    const LIMIT_ZERO: &str = include_str!("fixtures/explain_limit_zero.json");
+    //   EXPLAIN (ANALYZE, FORMAT JSON) SELECT * FROM orders WHERE false
+    const WHERE_FALSE: &str = include_str!("fixtures/explain_where_false.json");
+    // With parallel setup and tuple costs at 0 and two workers per gather:
+    //   EXPLAIN (ANALYZE, FORMAT JSON) SELECT count(*) FROM orders WHERE total > 50
+    const PARALLEL: &str = include_str!("fixtures/explain_parallel.json");
+
+    fn parse(fixture: &str) -> QueryPlan {
+        parse_plan(serde_json::from_str(fixture).unwrap(), true, false).unwrap()
+    }
+
+    fn assert_close(actual: Option<f64>, expected: f64) {
+        let actual = actual.expect("value missing");
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn parses_the_node_tree() {
+        let plan = parse(NESTED_LOOP);
+        assert_close(plan.planning_time_ms, 0.731);
+        assert_close(plan.execution_time_ms, 13.051);
+        assert!(plan.analyzed);
+
+        let aggregate = &plan.root;
+        let sort = &aggregate.children[0];
+        let join = &sort.children[0];
+        let (scan, index_scan) = (&join.children[0], &join.children[1]);
+        assert_eq!(
+            [&aggregate.node_type, &sort.node_type, &join.node_type, &scan.node_type, &index_scan.node_type],
+            ["Aggregate", "Sort", "Nested Loop", "Seq Scan", "Index Scan"]
+        );
+        assert_eq!(join.join_type.as_deref(), Some("Inner"));
+        assert_eq!(scan.relation_name.as_deref(), Some("orders"));
+        assert_eq!(scan.rows_removed_by_filter, Some(16667.0));
+        assert_eq!(index_scan.parent_relationship.as_deref(), Some("Inner"));
+        assert_eq!(index_scan.index_name.as_deref(), Some("customers_pkey"));
+        assert_eq!(index_scan.index_cond.as_deref(), Some("(id = o.customer_id)"));
+        assert_eq!(index_scan.buffers.as_ref().map(|b| b.shared_hit), Some(9999));
+        // Without BUFFERS there are no counters at all
+        assert!(parse(LIMIT_ZERO).root.buffers.is_none());
+    }
+
+    #[test]
+    fn exclusive_time_multiplies_loops_and_subtracts_children() {
+        let plan = parse(NESTED_LOOP);
+        let aggregate = &plan.root;
+        let sort = &aggregate.children[0];
+        let join = &sort.children[0];
+        let (scan, index_scan) = (&join.children[0], &join.children[1]);
+
+        // 0.002 ms per loop over 3333 loops
+        assert_close(index_scan.exclusive_time_ms, 6.666);
+        assert_close(scan.exclusive_time_ms, 2.999);
+        // 10.673 less the scan's 2.999 and the index scan's 6.666
+        assert_close(join.exclusive_time_ms, 1.008);
+        assert_close(sort.exclusive_time_ms, 12.292 - 10.673);
+        assert_close(aggregate.exclusive_time_ms, 12.885 - 12.292);
+
+        // Every millisecond of the root is accounted to exactly one node
+        let nodes = [aggregate, sort, join, scan, index_scan];
+        let total: f64 = nodes.iter().map(|node| node.exclusive_time_ms.unwrap()).sum();
+        assert_close(Some(total), 12.885);
+    }
+
+    #[test]
+    fn exclusive_time_shares_parallel_loops_between_processes() {
+        let plan = parse(PARALLEL);
+        let finalize = &plan.root;
+        let gather = &finalize.children[0];
+        let partial = &gather.children[0];
+        let scan = &partial.children[0];
+        assert_eq!(gather.node_type, "Gather");
+        assert_eq!([partial.actual_loops, scan.actual_loops], [Some(3.0), Some(3.0)]);
+
+        // The leader and two workers each ran the partial aggregate and the scan once
+        assert_close(scan.exclusive_time_ms, 0.811);
+        assert_close(partial.exclusive_time_ms, 0.185);
+        assert_close(gather.exclusive_time_ms, 17.403);
+        assert_close(finalize.exclusive_time_ms, 0.019);
+
+        let nodes = [finalize, gather, partial, scan];
+        let total: f64 = nodes.iter().map(|node| node.exclusive_time_ms.unwrap()).sum();
+        assert_close(Some(total), 18.418);
+    }
+
+    #[test]
+    fn exclusive_time_is_clamped_at_zero() {
+        // Timing overhead can leave a child a little over its parent
+        let raw = serde_json::json!([{ "Plan": {
+            "Node Type": "Gather", "Plan Rows": 10, "Actual Total Time": 5.0, "Actual Rows": 10, "Actual Loops": 1,
+            "Workers Launched": 2,
+            "Plans": [{ "Node Type": "Seq Scan", "Plan Rows": 4, "Actual Total Time": 5.1, "Actual Rows": 3, "Actual Loops": 3 }]
+        }}]);
+        let plan = parse_plan(raw, true, false).unwrap();
+        assert_close(plan.root.exclusive_time_ms, 0.0);
+        assert_close(plan.root.children[0].exclusive_time_ms, 5.1);
+    }
+
+    #[test]
+    fn row_estimate_ratio_compares_actual_to_planned_rows() {
+        let plan = parse(NESTED_LOOP);
+        let join = &plan.root.children[0].children[0];
+        // Planned for 1 row, got 3333
+        assert_close(join.row_estimate_ratio, 3333.0);
+        // Rows are per loop on both sides
+        assert_close(join.children[1].row_estimate_ratio, 1.0);
+
+        let plan = parse(LIMIT_ZERO);
+        // An empty result against an estimate of one row is a match
+        assert_close(plan.root.row_estimate_ratio, 1.0);
+        // The scan under LIMIT 0 never ran, so its 9800-row estimate isn't a miss
+        let scan = &plan.root.children[0];
+        assert_eq!(scan.actual_loops, Some(0.0));
+        assert_eq!(scan.row_estimate_ratio, None);
+        assert_close(scan.exclusive_time_ms, 0.0);
+    }
+
+    #[test]
+    fn zero_row_estimates_do_not_divide_by_zero() {
+        let plan = parse(WHERE_FALSE);
+        assert_eq!(plan.root.plan_rows, 0.0);
+        assert_close(plan.root.row_estimate_ratio, 1.0);
+
+        let raw = serde_json::json!([{ "Plan": {
+            "Node Type": "Result", "Plan Rows": 0, "Actual Total Time": 0.01, "Actual Rows": 25, "Actual Loops": 1
+        }}]);
+        assert_close(parse_plan(raw, true, false).unwrap().root.row_estimate_ratio, 25.0);
+    }
+
+    #[test]
+    fn plain_explain_has_no_actuals() {
+        let raw = serde_json::json!([{ "Plan": { "Node Type": "Seq Scan", "Plan Rows": 100, "Total Cost": 12.5 } }]);
+        let plan = parse_plan(raw, false, false).unwrap();
+        assert_eq!(plan.root.exclusive_time_ms, None);
+        assert_eq!(plan.root.row_estimate_ratio, None);
+        assert_eq!(plan.execution_time_ms, None);
+
+        assert!(parse_plan(serde_json::json!({}), false, false).is_err());
+        assert!(parse_plan(serde_json::json!([{}]), false, false).is_err());
+    }
+}
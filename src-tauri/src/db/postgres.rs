@@ -1,98 +1,1768 @@
 use async_trait::async_trait;
-use sqlx::postgres::{PgPoolOptions, PgRow};
-use sqlx::{Column, Row, TypeInfo};
-use std::time::Duration;
+use sqlx::pool::PoolConnection;
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::postgres::types::Oid;
+use sqlx::postgres::{PgArgumentBuffer, PgArguments, PgConnectOptions, PgConnection, PgPoolOptions, PgSslMode, PgRow, PgTypeInfo, PgValueFormat};
+use sqlx::{Arguments, Column, Connection, Either, Encode, Executor, Postgres, Row, Statement, Transaction, Type, TypeInfo};
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use chrono::{DateTime, Utc, NaiveDateTime, NaiveDate, NaiveTime};
 
-use crate::db::DatabaseDriver;
-use crate::models::{DbConfig, QueryResult};
+use crate::db::connstring::parse_startup_options;
+use crate::db::meta::{message_result, parse_meta_command, pattern_regexes, split_meta_commands, MetaCommand, ScriptPart};
+use crate::db::notices::{collect_notices, drain_notices, NoticeBuffer};
+use crate::db::plan::parse_plan;
+use crate::db::splitter::{classify_statement, classify_statements, split_statements, transaction_status_after};
+use crate::db::variables::quote_identifier;
+use crate::error::AppError;
+use crate::db::{DatabaseDriver, LocalMetaCommands, QueryCursor};
+use crate::models::{
+    DbConfig, ErrorPolicy, ExecutionLimits, ExplainOptions, QueryOptions, QueryParam, QueryPlan, QueryResult, QueryStreamEvent,
+    PoolSettings, PoolStatus, QuerySummary, ResultColumn,
+    ScriptReport, ServerNotice, StatementDescription, StatementKind, StatementReport, StatementSpan,
+    SessionOptions, SslMode, StatementStatus, TransactionStatus,
+};
+
+// Postgres error code raised when a statement is interrupted by a cancel request
+const QUERY_CANCELED: &str = "57014";
+const CANCEL_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct RunningQuery {
+    backend_pid: i32,
+    cancel_requested: bool,
+}
+
+type RunningQueries = Mutex<HashMap<String, RunningQuery>>;
+
+// A connection taken out of the pool and reserved for one tab
+struct PinnedSession {
+    conn: Arc<tokio::sync::Mutex<PinnedConnection>>,
+    // Pinned only to hold an explicit transaction; released again on commit or rollback
+    transaction_only: bool,
+}
 
 pub struct PostgresDriver {
     pool: Option<sqlx::PgPool>,
+    // Kept to open connections outside the pool, such as the one a cancel is sent on
+    connect_options: Option<PgConnectOptions>,
+    // In-flight queries keyed by the caller supplied query id (usually the tab id)
+    // Shared with open cursors so a page fetch can be cancelled like a query
+    running: Arc<RunningQueries>,
+    // Pinned connections keyed by session id (the tab id)
+    sessions: Mutex<HashMap<String, PinnedSession>>,
+    // Pin a connection for every session on first use instead of waiting for pin_session
+    auto_pin_sessions: bool,
+    // From the saved connection; per-query limits override them field by field
+    default_limits: ExecutionLimits,
+    pool_settings: PoolSettings,
+    // The session options as applied to each new connection, kept to restore them after a reset
+    session_settings: Arc<Vec<(String, String)>>,
 }
 
 impl PostgresDriver {
     pub fn new() -> Self {
-        Self { pool: None }
+        Self {
+            pool: None,
+            connect_options: None,
+            running: Arc::new(Mutex::new(HashMap::new())),
+            sessions: Mutex::new(HashMap::new()),
+            auto_pin_sessions: false,
+            default_limits: ExecutionLimits::default(),
+            pool_settings: PoolSettings::default(),
+            session_settings: Arc::new(Vec::new()),
+        }
+    }
+
+    pub fn set_auto_pin_sessions(&mut self, enabled: bool) {
+        self.auto_pin_sessions = enabled;
+    }
+
+    // Takes effect on the next connect
+    pub fn set_pool_settings(&mut self, settings: PoolSettings) {
+        self.pool_settings = settings;
+    }
+
+    fn max_pinned_sessions(&self) -> u32 {
+        self.pool_settings.max_pinned_sessions.unwrap_or(DEFAULT_MAX_PINNED_SESSIONS)
+    }
+
+    // Pinned connections are detached from the pool, so they're capped on their own
+    fn check_pinned_capacity(&self, sessions: &HashMap<String, PinnedSession>) -> Result<(), AppError> {
+        let max = self.max_pinned_sessions();
+        if sessions.len() as u32 >= max {
+            return Err(AppError::connection(format!(
+                "{} tabs already hold a connection of their own, the most this connection allows; \
+                 end their transactions or close one of them, or raise the limit in the pool settings",
+                max
+            )));
+        }
+        Ok(())
+    }
+
+    fn limits_for(&self, options: &QueryOptions) -> ExecutionLimits {
+        options.limits.or(&self.default_limits)
+    }
+
+    fn pinned_connection(&self, session_id: &str) -> Result<Option<Arc<tokio::sync::Mutex<PinnedConnection>>>, AppError> {
+        let sessions = self.sessions.lock()?;
+        Ok(sessions.get(session_id).map(|s| s.conn.clone()))
+    }
+
+    // Runs on the tab's pinned connection when there is one, otherwise on any pooled connection
+    async fn connection_for(&self, options: &QueryOptions) -> Result<SessionConnection, AppError> {
+        let pool = self.pool.as_ref().ok_or_else(|| AppError::connection("Not connected"))?;
+
+        if let Some(session_id) = options.session_id.as_deref() {
+            if let Some(conn) = self.pinned_connection(session_id)? {
+                return Ok(SessionConnection::Pinned(conn.lock_owned().await));
+            }
+            if self.auto_pin_sessions {
+                let conn = self.pin_connection(session_id, false).await?;
+                return Ok(SessionConnection::Pinned(conn.lock_owned().await));
+            }
+        }
+
+        Ok(SessionConnection::Pooled(pool.acquire().await?))
+    }
+
+    async fn pin_connection(&self, session_id: &str, transaction_only: bool) -> Result<Arc<tokio::sync::Mutex<PinnedConnection>>, AppError> {
+        {
+            let mut sessions = self.sessions.lock()?;
+            if let Some(session) = sessions.get_mut(session_id) {
+                // An explicit pin outlives the transaction that may have pinned it first
+                session.transaction_only &= transaction_only;
+                return Ok(session.conn.clone());
+            }
+            self.check_pinned_capacity(&sessions)?;
+        }
+
+        let pool = self.pool.as_ref().ok_or_else(|| AppError::connection("Not connected"))?;
+        // Detached so session state never leaks back into the pool, which may open a replacement
+        let conn = pool.acquire().await?.detach();
+
+        let (conn, error) = {
+            let mut sessions = self.sessions.lock()?;
+            // Other tabs may have pinned connections while this one waited on the pool
+            match self.check_pinned_capacity(&sessions) {
+                Err(e) if !sessions.contains_key(session_id) => (conn, e),
+                _ => {
+                    let session = sessions
+                        .entry(session_id.to_string())
+                        .or_insert_with(|| PinnedSession {
+                            conn: Arc::new(tokio::sync::Mutex::new(PinnedConnection { conn, status: TransactionStatus::Idle })),
+                            transaction_only,
+                        });
+                    return Ok(session.conn.clone());
+                }
+            }
+        };
+        let _ = conn.close().await;
+        Err(error)
+    }
+
+    async fn unpin_connection(&self, session_id: &str) -> Result<(), AppError> {
+        let session = {
+            let mut sessions = self.sessions.lock()?;
+            sessions.remove(session_id)
+        };
+
+        if let Some(session) = session {
+            // A query still running on it holds the last reference; dropping that closes the socket
+            if let Ok(conn) = Arc::try_unwrap(session.conn) {
+                conn.into_inner().conn.close().await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn end_transaction(&self, session_id: &str, statement: &str) -> Result<TransactionStatus, AppError> {
+        let (conn, transaction_only) = {
+            let sessions = self.sessions.lock()?;
+            let session = sessions
+                .get(session_id)
+                .ok_or_else(|| AppError::not_found("No transaction is open for this tab"))?;
+            (session.conn.clone(), session.transaction_only)
+        };
+
+        let status = {
+            let mut conn = SessionConnection::Pinned(conn.lock_owned().await);
+            execute_tracked(&mut conn, statement).await?;
+            conn.status()
+        };
+
+        if transaction_only && status == TransactionStatus::Idle {
+            self.unpin_connection(session_id).await?;
+        }
+        Ok(status)
+    }
+
+    // Catalog query for a meta-command, labelled as the command rather than as a SELECT
+    async fn catalog_query(
+        &self,
+        conn: &mut SessionConnection,
+        sql: &str,
+        params: Vec<QueryParam>,
+        command_tag: &str,
+        options: &QueryOptions,
+    ) -> Result<QueryResult, AppError> {
+        let options = QueryOptions { params, ..options.clone() };
+        let mut result = self
+            .statement_results(conn, sql, &options)
+            .await?
+            .pop()
+            .ok_or_else(|| AppError::other("Catalog query returned no result"))?;
+        result.query_type = "META".to_string();
+        result.command_tag = command_tag.to_string();
+        result.span = None;
+        Ok(result)
+    }
+
+    // Runs an EXPLAIN statement, inside a scope that is rolled back when `rollback` is set.
+    // Returns the plan JSON and whether it was rolled back.
+    async fn explain_plan(
+        &self,
+        conn: &mut SessionConnection,
+        sql: &str,
+        rollback: bool,
+        options: &QueryOptions,
+    ) -> Result<(serde_json::Value, bool), AppError> {
+        let scope = match rollback {
+            true => Some(ScriptScope::open(conn).await?.ok_or_else(|| {
+                AppError::other("The current transaction is aborted; roll it back before running EXPLAIN ANALYZE")
+            })?),
+            false => None,
+        };
+
+        let results = self.statement_results(conn, sql, options).await;
+        if let Some(scope) = &scope {
+            execute_tracked(conn, &scope.close_sql(true)).await?;
+        }
+
+        let result = results?
+            .pop()
+            .ok_or_else(|| AppError::other("EXPLAIN returned no result"))?;
+        if result.cancelled {
+            return Err(AppError::other("EXPLAIN was cancelled"));
+        }
+
+        let plan = result
+            .rows
+            .into_iter()
+            .next()
+            .and_then(|row| row.into_iter().next())
+            .ok_or_else(|| AppError::other("EXPLAIN returned no plan"))?;
+        Ok((plan, scope.is_some()))
+    }
+
+    // `\d pattern`: a column listing for each matching relation, followed by its indexes
+    async fn describe_relations(
+        &self,
+        conn: &mut SessionConnection,
+        pattern: &str,
+        options: &QueryOptions,
+    ) -> Result<Vec<QueryResult>, AppError> {
+        let mut params = Vec::new();
+        let filter = pattern_filter(Some(pattern), "n.nspname", "c.relname", "pg_table_is_visible(c.oid)", &mut params);
+        let sql = format!(
+            "SELECT n.nspname, c.relname, {} \
+             FROM pg_class c JOIN pg_namespace n ON n.oid = c.relnamespace \
+             WHERE c.relkind IN ('r', 'p', 'v', 'm', 'S', 'f', 'i', 'I') AND {} \
+             ORDER BY 1, 2",
+            RELKIND_LABEL, filter
+        );
+        let relations = self.catalog_query(conn, &sql, params, "\\d", options).await?;
+        if relations.rows.is_empty() {
+            return Err(AppError::not_found(format!("Did not find any relation named \"{}\".", pattern)));
+        }
+
+        let mut results = Vec::new();
+        for row in &relations.rows {
+            let text = |i: usize| row.get(i).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+            let (schema, name, kind) = (text(0), text(1), text(2));
+            let relation = format!("{}.{}", quote_identifier(&schema), quote_identifier(&name));
+            let title = format!("{} \"{}.{}\"", capitalize(&kind), schema, name);
+
+            results.push(
+                self.catalog_query(conn, DESCRIBE_COLUMNS, vec![QueryParam::Text(relation.clone())], &title, options)
+                    .await?,
+            );
+
+            let indexes = self
+                .catalog_query(conn, DESCRIBE_INDEXES, vec![QueryParam::Text(relation)], &format!("Indexes of {}", title), options)
+                .await?;
+            if !indexes.rows.is_empty() {
+                results.push(indexes);
+            }
+        }
+        Ok(results)
+    }
+
+    // The catalog results answering a meta-command
+    async fn meta_results(
+        &self,
+        conn: &mut SessionConnection,
+        command: &MetaCommand,
+        options: &QueryOptions,
+    ) -> Result<Vec<QueryResult>, AppError> {
+        if let MetaCommand::Describe { pattern: Some(pattern) } = command {
+            return self.describe_relations(conn, pattern, options).await;
+        }
+
+        let (sql, params) = list_query(command)?;
+        let tag = match command {
+            MetaCommand::Describe { .. } => "List of relations",
+            MetaCommand::ListTables { .. } => "List of tables",
+            MetaCommand::ListFunctions { .. } => "List of functions",
+            MetaCommand::ListSchemas { .. } => "List of schemas",
+            _ => "List of databases",
+        };
+        Ok(vec![self.catalog_query(conn, &sql, params, tag, options).await?])
+    }
+
+    // A backslash command met inside a script or stream, run on the connection the rest of it
+    // uses so it sees the script's own transaction
+    async fn inline_meta_command(
+        &self,
+        conn: &mut SessionConnection,
+        text: &str,
+        span: &StatementSpan,
+        options: &QueryOptions,
+        local_meta: &LocalMetaCommands<'_>,
+    ) -> Result<Vec<QueryResult>, AppError> {
+        let command = parse_meta_command(text)?;
+        if let Some(message) = local_meta(&command) {
+            return Ok(vec![message_result(text, &message?, span.clone())]);
+        }
+
+        let mut results = self.meta_results(conn, &command, options).await?;
+        for result in &mut results {
+            result.span = Some(span.clone());
+        }
+        Ok(results)
+    }
+
+    // One result per statement the server reports, with a final cancelled one if the run was interrupted
+    async fn statement_results(
+        &self,
+        conn: &mut SessionConnection,
+        sql: &str,
+        options: &QueryOptions,
+    ) -> Result<Vec<QueryResult>, AppError> {
+        let mut results = Vec::new();
+        let mut columns = Vec::new();
+        let mut rows = Vec::new();
+        let mut statement_started = Instant::now();
+
+        let outcome = self
+            .execute(conn, sql, options, |item| match item {
+                ExecItem::Columns(c) => columns = c,
+                ExecItem::Row(row) => rows.push(row),
+                ExecItem::StatementDone { affected_rows, notices, truncated } => {
+                    let mut result = statement_result(
+                        std::mem::take(&mut columns),
+                        std::mem::take(&mut rows),
+                        affected_rows,
+                        statement_started.elapsed().as_millis() as u64,
+                        notices,
+                        false,
+                    );
+                    result.truncated = truncated;
+                    results.push(result);
+                    statement_started = Instant::now();
+                }
+            })
+            .await?;
+
+        if outcome.cancelled {
+            let duration_ms = statement_started.elapsed().as_millis() as u64;
+            let mut result = statement_result(columns, rows, 0, duration_ms, outcome.notices, true);
+            result.truncated = outcome.truncated;
+            results.push(result);
+        }
+
+        Ok(results)
+    }
+
+    fn cancel_requested(&self, query_id: &str) -> bool {
+        cancel_requested(&self.running, query_id)
+    }
+
+    // Shared fetch_many loop: reports each statement's column names, its rows as they
+    // arrive, and then its completion
+    async fn execute<F>(
+        &self,
+        session: &mut SessionConnection,
+        sql: &str,
+        options: &QueryOptions,
+        mut on_item: F,
+    ) -> Result<ExecOutcome, AppError>
+    where
+        F: FnMut(ExecItem) + Send,
+    {
+        use futures::StreamExt;
+
+        let conn: &mut PgConnection = session;
+        let mut outcome = ExecOutcome { affected_rows: 0, cancelled: false, truncated: false, notices: Vec::new() };
+        let mut has_columns = false;
+        let mut statement_rows = 0;
+
+        let query_id = options.query_id.as_deref();
+        let max_rows = self.limits_for(options).max_rows.unwrap_or(usize::MAX);
+
+        let _guard = match query_id {
+            Some(id) => {
+                let backend_pid: i32 = sqlx::query_scalar("SELECT pg_backend_pid()")
+                    .fetch_one(&mut *conn)
+                    .await?;
+
+                Some(RunningQueryGuard::register(&self.running, id, backend_pid)?)
+            }
+            None => None,
+        };
+
+        let notices = NoticeBuffer::default();
+        let params = bind_params(&options.params)?;
+        let mut statements_done = 0;
+        // The first statement's completion, held back while it has reported no columns: if no
+        // other statement follows, its columns are described before it's reported
+        let mut held: Option<ExecItem> = None;
+
+        let run = async {
+            let mut stream = match params {
+                // Bind values need the extended protocol, which takes a single statement
+                Some(args) => conn.fetch_many(sqlx::query_with(sql, args).persistent(false)),
+                // Simple query protocol so scripts with several statements run as one batch
+                None => conn.fetch_many(sqlx::raw_sql(sql)),
+            };
+
+            while let Some(result) = stream.next().await {
+                // Something follows, so the held completion goes out as it is
+                if let Some(done) = held.take() {
+                    on_item(done);
+                }
+
+                let item = match result {
+                    Ok(item) => item,
+                    Err(e) if is_query_canceled(&e) && query_id.is_some_and(|id| self.cancel_requested(id)) => {
+                        outcome.cancelled = true;
+                        break;
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+
+                match item {
+                    Either::Left(res) => {
+                        outcome.affected_rows += res.rows_affected();
+                        statement_rows = 0;
+                        statements_done += 1;
+                        let done = ExecItem::StatementDone {
+                            affected_rows: res.rows_affected(),
+                            notices: drain_notices(&notices),
+                            truncated: std::mem::take(&mut outcome.truncated),
+                        };
+                        if statements_done == 1 && !has_columns {
+                            held = Some(done);
+                        } else {
+                            on_item(done);
+                        }
+                        has_columns = false;
+                    }
+                    // Past the limit rows are still read off the socket, just not kept
+                    Either::Right(_) if statement_rows >= max_rows => outcome.truncated = true,
+                    Either::Right(row) => {
+                        statement_rows += 1;
+                        if !has_columns {
+                            has_columns = true;
+                            on_item(ExecItem::Columns(
+                                row.columns().iter().map(|c| c.name().to_string()).collect(),
+                            ));
+                        }
+
+                        let mut row_values = Vec::new();
+                        for (i, _) in row.columns().iter().enumerate() {
+                             let value = map_postgres_value(&row, i);
+                             row_values.push(value);
+                        }
+                        on_item(ExecItem::Row(row_values));
+                    }
+                }
+            }
+            drop(stream);
+
+            if let Some(done) = held.take() {
+                if !outcome.cancelled {
+                    if let Some(columns) = describe_columns(conn, sql, &options.params).await {
+                        on_item(ExecItem::Columns(columns));
+                    }
+                }
+                on_item(done);
+            }
+
+            outcome.notices = drain_notices(&notices);
+            Ok::<_, AppError>(outcome)
+        };
+
+        let outcome = collect_notices(notices.clone(), run).await;
+        let interrupted = outcome.as_ref().map_or(true, |outcome| outcome.cancelled);
+        session.track(sql, statements_done, interrupted);
+        outcome
+    }
+}
+
+const RELKIND_LABEL: &str = "CASE c.relkind \
+    WHEN 'r' THEN 'table' WHEN 'p' THEN 'partitioned table' WHEN 'v' THEN 'view' \
+    WHEN 'm' THEN 'materialized view' WHEN 'S' THEN 'sequence' WHEN 'f' THEN 'foreign table' \
+    WHEN 'i' THEN 'index' WHEN 'I' THEN 'partitioned index' END";
+
+const DESCRIBE_COLUMNS: &str = r#"
+    SELECT
+        a.attname AS "Column",
+        format_type(a.atttypid, a.atttypmod) AS "Type",
+        (SELECT co.collname FROM pg_collation co JOIN pg_type t ON t.oid = a.atttypid
+          WHERE co.oid = a.attcollation AND a.attcollation <> t.typcollation) AS "Collation",
+        CASE WHEN a.attnotnull THEN 'not null' END AS "Nullable",
+        pg_get_expr(d.adbin, d.adrelid) AS "Default"
+    FROM pg_attribute a
+    LEFT JOIN pg_attrdef d ON d.adrelid = a.attrelid AND d.adnum = a.attnum
+    WHERE a.attrelid = $1::regclass AND a.attnum > 0 AND NOT a.attisdropped
+    ORDER BY a.attnum
+"#;
+
+const DESCRIBE_INDEXES: &str = r#"
+    SELECT i.relname AS "Index", pg_get_indexdef(i.oid) AS "Definition"
+    FROM pg_index x
+    JOIN pg_class i ON i.oid = x.indexrelid
+    WHERE x.indrelid = $1::regclass
+    ORDER BY x.indisprimary DESC, i.relname
+"#;
+
+// SQL and bind values for the list-style meta-commands
+fn list_query(command: &MetaCommand) -> Result<(String, Vec<QueryParam>), AppError> {
+    let mut params = Vec::new();
+
+    let sql = match command {
+        MetaCommand::Describe { pattern: None } | MetaCommand::ListTables { pattern: _ } => {
+            let (kinds, pattern) = match command {
+                MetaCommand::ListTables { pattern } => ("'r', 'p'", pattern.as_deref()),
+                _ => ("'r', 'p', 'v', 'm', 'S', 'f'", None),
+            };
+            format!(
+                r#"SELECT n.nspname AS "Schema", c.relname AS "Name", {} AS "Type",
+                       pg_get_userbyid(c.relowner) AS "Owner"
+                   FROM pg_class c JOIN pg_namespace n ON n.oid = c.relnamespace
+                   WHERE c.relkind IN ({}) AND {}
+                   ORDER BY 1, 2"#,
+                RELKIND_LABEL,
+                kinds,
+                pattern_filter(pattern, "n.nspname", "c.relname", "pg_table_is_visible(c.oid)", &mut params)
+            )
+        }
+        MetaCommand::ListFunctions { pattern } => format!(
+            r#"SELECT n.nspname AS "Schema", p.proname AS "Name",
+                   pg_get_function_result(p.oid) AS "Result data type",
+                   pg_get_function_arguments(p.oid) AS "Argument data types",
+                   CASE p.prokind WHEN 'a' THEN 'agg' WHEN 'w' THEN 'window' WHEN 'p' THEN 'proc' ELSE 'func' END AS "Type"
+               FROM pg_proc p JOIN pg_namespace n ON n.oid = p.pronamespace
+               WHERE {}
+               ORDER BY 1, 2, 4"#,
+            pattern_filter(pattern.as_deref(), "n.nspname", "p.proname", "pg_function_is_visible(p.oid)", &mut params)
+        ),
+        MetaCommand::ListSchemas { pattern } => {
+            let filter = match pattern {
+                Some(pattern) => {
+                    params.push(QueryParam::Text(pattern_regexes(pattern).1));
+                    "n.nspname ~ $1"
+                }
+                None => "n.nspname !~ '^pg_' AND n.nspname <> 'information_schema'",
+            };
+            format!(
+                r#"SELECT n.nspname AS "Name", pg_get_userbyid(n.nspowner) AS "Owner"
+                   FROM pg_namespace n
+                   WHERE {}
+                   ORDER BY 1"#,
+                filter
+            )
+        }
+        MetaCommand::ListDatabases { pattern } => {
+            let filter = match pattern {
+                Some(pattern) => {
+                    params.push(QueryParam::Text(pattern_regexes(pattern).1));
+                    "d.datname ~ $1"
+                }
+                None => "true",
+            };
+            format!(
+                r#"SELECT d.datname AS "Name", pg_get_userbyid(d.datdba) AS "Owner",
+                       pg_encoding_to_char(d.encoding) AS "Encoding", d.datcollate AS "Collate",
+                       d.datctype AS "Ctype", array_to_string(d.datacl, E'\n') AS "Access privileges"
+                   FROM pg_database d
+                   WHERE {}
+                   ORDER BY 1"#,
+                filter
+            )
+        }
+        MetaCommand::Describe { pattern: Some(_) } | MetaCommand::ExpandedDisplay(_) => {
+            return Err(AppError::other("Not a listing command"));
+        }
+    };
+
+    Ok((sql, params))
+}
+
+// Matches psql: without a pattern only user objects on the search path are listed, a bare
+// name is matched among visible objects, and `schema.name` matches anywhere
+fn pattern_filter(
+    pattern: Option<&str>,
+    schema_column: &str,
+    name_column: &str,
+    visible: &str,
+    params: &mut Vec<QueryParam>,
+) -> String {
+    let Some(pattern) = pattern else {
+        return format!(
+            "{} NOT IN ('pg_catalog', 'information_schema') AND {} !~ '^pg_toast' AND {}",
+            schema_column, schema_column, visible
+        );
+    };
+
+    let (schema, name) = pattern_regexes(pattern);
+    params.push(QueryParam::Text(name));
+    let mut filter = format!("{} ~ ${}", name_column, params.len());
+
+    match schema {
+        Some(schema) => {
+            params.push(QueryParam::Text(schema));
+            filter.push_str(&format!(" AND {} ~ ${}", schema_column, params.len()));
+        }
+        None => {
+            filter.push_str(" AND ");
+            filter.push_str(visible);
+        }
+    }
+    filter
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+// A pinned connection and the transaction status its statements have left it in. sqlx keeps
+// the status the server reports to itself, so it's followed here from what runs.
+struct PinnedConnection {
+    conn: PgConnection,
+    status: TransactionStatus,
+}
+
+enum SessionConnection {
+    Pooled(PoolConnection<Postgres>),
+    Pinned(tokio::sync::OwnedMutexGuard<PinnedConnection>),
+}
+
+impl SessionConnection {
+    // A pooled connection is handed out between calls, so outside any transaction
+    fn status(&self) -> TransactionStatus {
+        match self {
+            SessionConnection::Pooled(_) => TransactionStatus::Idle,
+            SessionConnection::Pinned(conn) => conn.status,
+        }
+    }
+
+    // Follows the first `completed` statements of `sql`, then when `interrupted` the one
+    // after them, which failed or was cancelled
+    fn track(&mut self, sql: &str, completed: usize, interrupted: bool) {
+        let SessionConnection::Pinned(conn) = self else {
+            return;
+        };
+        let spans = split_statements(sql);
+        let statement = |i: usize| spans.get(i).map_or("", |span| &sql[span.start..span.end]);
+        for i in 0..completed.min(spans.len()) {
+            conn.status = transaction_status_after(conn.status, statement(i), true);
+        }
+        if interrupted {
+            conn.status = transaction_status_after(conn.status, statement(completed), false);
+        }
+    }
+}
+
+impl Deref for SessionConnection {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &PgConnection {
+        match self {
+            SessionConnection::Pooled(conn) => conn,
+            SessionConnection::Pinned(conn) => &conn.conn,
+        }
+    }
+}
+
+impl DerefMut for SessionConnection {
+    fn deref_mut(&mut self) -> &mut PgConnection {
+        match self {
+            SessionConnection::Pooled(conn) => conn,
+            SessionConnection::Pinned(conn) => &mut conn.conn,
+        }
+    }
+}
+
+// A plain &str runs over the simple query protocol, without preparing a statement
+async fn execute_simple(conn: &mut PgConnection, sql: &str) -> Result<(), AppError> {
+    conn.execute(sql).await?;
+    Ok(())
+}
+
+// execute_simple for statements that begin or end transactions
+async fn execute_tracked(conn: &mut SessionConnection, sql: &str) -> Result<(), AppError> {
+    let result = execute_simple(conn, sql).await;
+    let completed = if result.is_ok() { usize::MAX } else { 0 };
+    conn.track(sql, completed, result.is_err());
+    result
+}
+
+// None means the query has no placeholders and can go over the simple query protocol
+fn bind_params(params: &[QueryParam]) -> Result<Option<PgArguments>, AppError> {
+    if params.is_empty() {
+        return Ok(None);
+    }
+
+    let mut args = PgArguments::default();
+    for (i, param) in params.iter().enumerate() {
+        let added = match param {
+            QueryParam::Text(s) => args.add(s.as_str()),
+            QueryParam::Number(n) => match n.as_i64() {
+                Some(v) => args.add(v),
+                None => args.add(number_as_f64(n, i)?),
+            },
+            QueryParam::Bool(b) => args.add(*b),
+            QueryParam::Null => args.add(UntypedNull),
+            QueryParam::Json(v) => args.add(v),
+            QueryParam::TextArray(items) => args.add(items),
+            QueryParam::NumberArray(items) => {
+                if items.iter().flatten().all(|n| n.is_i64()) {
+                    args.add(items.iter().map(|n| n.as_ref().and_then(|n| n.as_i64())).collect::<Vec<_>>())
+                } else {
+                    let floats = items
+                        .iter()
+                        .map(|n| n.as_ref().map(|n| number_as_f64(n, i)).transpose())
+                        .collect::<Result<Vec<_>, _>>()?;
+                    args.add(floats)
+                }
+            }
+            QueryParam::BoolArray(items) => args.add(items),
+        };
+        added.map_err(|e| AppError::other(format!("Cannot bind parameter ${}: {}", i + 1, e)))?;
+    }
+    Ok(Some(args))
+}
+
+// The types bind_params declares for the same values
+fn param_types(params: &[QueryParam]) -> Vec<PgTypeInfo> {
+    params
+        .iter()
+        .map(|param| match param {
+            QueryParam::Text(_) => <String as Type<Postgres>>::type_info(),
+            QueryParam::Number(n) if n.is_i64() => <i64 as Type<Postgres>>::type_info(),
+            QueryParam::Number(_) => <f64 as Type<Postgres>>::type_info(),
+            QueryParam::Bool(_) => <bool as Type<Postgres>>::type_info(),
+            QueryParam::Null => UntypedNull::type_info(),
+            QueryParam::Json(_) => <serde_json::Value as Type<Postgres>>::type_info(),
+            QueryParam::TextArray(_) => <Vec<Option<String>> as Type<Postgres>>::type_info(),
+            QueryParam::NumberArray(items) if items.iter().flatten().all(|n| n.is_i64()) => {
+                <Vec<Option<i64>> as Type<Postgres>>::type_info()
+            }
+            QueryParam::NumberArray(_) => <Vec<Option<f64>> as Type<Postgres>>::type_info(),
+            QueryParam::BoolArray(_) => <Vec<Option<bool>> as Type<Postgres>>::type_info(),
+        })
+        .collect()
+}
+
+fn number_as_f64(n: &serde_json::Number, index: usize) -> Result<f64, AppError> {
+    n.as_f64()
+        .ok_or_else(|| AppError::other(format!("Parameter ${} is not a representable number", index + 1)))
+}
+
+// NULL sent with type OID 0, which leaves the parameter's type for the server to infer.
+// Any other declared type would have to match the column it ends up in.
+struct UntypedNull;
+
+impl Type<Postgres> for UntypedNull {
+    fn type_info() -> PgTypeInfo {
+        PgTypeInfo::with_oid(Oid(0))
+    }
+}
+
+impl Encode<'_, Postgres> for UntypedNull {
+    fn encode_by_ref(&self, _buf: &mut PgArgumentBuffer) -> Result<IsNull, BoxDynError> {
+        Ok(IsNull::Yes)
+    }
+}
+
+// Column names of a statement that returned no rows, which the stream only carries on a
+// row. One Parse/Describe round trip, made only once the statement has run without error. The
+// text is made unique since sqlx caches what it prepares: a cached entry would answer for the
+// statement after its tables change, or be picked up by a parameterized run of it.
+async fn describe_columns(conn: &mut PgConnection, sql: &str, params: &[QueryParam]) -> Option<Vec<String>> {
+    let key = format!("{}\n-- columns {}", sql, uuid::Uuid::new_v4().simple());
+    let statement = conn.prepare_with(&key, &param_types(params)).await.ok()?;
+    let columns: Vec<String> = statement.columns().iter().map(|c| c.name().to_string()).collect();
+    (!columns.is_empty()).then_some(columns)
+}
+
+// Timeout settings a connection had before an execution overrode them
+struct PreviousTimeouts {
+    statement_timeout: String,
+    lock_timeout: String,
+}
+
+// Sets the timeouts for the session, returning what to restore afterwards. Pooled connections
+// are shared, so they must not keep one query's timeouts.
+async fn apply_timeouts(conn: &mut PgConnection, limits: &ExecutionLimits) -> Result<Option<PreviousTimeouts>, AppError> {
+    if limits.statement_timeout_ms.is_none() && limits.lock_timeout_ms.is_none() {
+        return Ok(None);
+    }
+
+    let row = conn
+        .fetch_one("SELECT current_setting('statement_timeout'), current_setting('lock_timeout')")
+        .await?;
+    let previous = PreviousTimeouts {
+        statement_timeout: row.try_get(0)?,
+        lock_timeout: row.try_get(1)?,
+    };
+
+    let statement_timeout = limits
+        .statement_timeout_ms
+        .map_or_else(|| previous.statement_timeout.clone(), |ms| ms.to_string());
+    let lock_timeout = limits
+        .lock_timeout_ms
+        .map_or_else(|| previous.lock_timeout.clone(), |ms| ms.to_string());
+    set_timeouts(conn, &statement_timeout, &lock_timeout).await?;
+
+    Ok(Some(previous))
+}
+
+async fn restore_timeouts(conn: &mut PgConnection, previous: Option<PreviousTimeouts>) {
+    if let Some(previous) = previous {
+        // In an aborted transaction this fails, but ending that transaction reverts the change anyway
+        let _ = set_timeouts(conn, &previous.statement_timeout, &previous.lock_timeout).await;
+    }
+}
+
+async fn set_timeouts(conn: &mut PgConnection, statement_timeout: &str, lock_timeout: &str) -> Result<(), AppError> {
+    sqlx::query("SELECT set_config('statement_timeout', $1, false), set_config('lock_timeout', $2, false)")
+        .bind(statement_timeout)
+        .bind(lock_timeout)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+async fn set_local(conn: &mut PgConnection, name: &str, value: &str) -> Result<(), AppError> {
+    sqlx::query("SELECT set_config($1, $2, true)")
+        .bind(name)
+        .bind(value)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+// How a script run under the rollback policy, or an analyzed write, is wrapped so it can be undone
+enum ScriptScope {
+    Transaction,
+    // The tab already has a transaction open, which the script must not end
+    Savepoint,
+}
+
+const SCRIPT_SAVEPOINT: &str = "pgmac_script";
+
+impl ScriptScope {
+    // None when the connection's transaction is aborted and nothing more can run in it
+    async fn open(conn: &mut SessionConnection) -> Result<Option<ScriptScope>, AppError> {
+        match conn.status() {
+            TransactionStatus::Idle => {
+                execute_tracked(conn, "BEGIN").await?;
+                Ok(Some(ScriptScope::Transaction))
+            }
+            TransactionStatus::Active => {
+                execute_tracked(conn, &format!("SAVEPOINT {}", SCRIPT_SAVEPOINT)).await?;
+                Ok(Some(ScriptScope::Savepoint))
+            }
+            TransactionStatus::Aborted => Ok(None),
+        }
+    }
+
+    fn close_sql(&self, rollback: bool) -> String {
+        match self {
+            ScriptScope::Transaction if rollback => "ROLLBACK".to_string(),
+            ScriptScope::Transaction => "COMMIT".to_string(),
+            ScriptScope::Savepoint if rollback => {
+                format!("ROLLBACK TO SAVEPOINT {0}; RELEASE SAVEPOINT {0}", SCRIPT_SAVEPOINT)
+            }
+            ScriptScope::Savepoint => format!("RELEASE SAVEPOINT {}", SCRIPT_SAVEPOINT),
+        }
+    }
+}
+
+// Only the isolation levels Postgres accepts, so the value can be spliced into BEGIN
+fn isolation_level_sql(level: &str) -> Result<&'static str, AppError> {
+    match level.trim().to_uppercase().as_str() {
+        "READ UNCOMMITTED" => Ok("READ UNCOMMITTED"),
+        "READ COMMITTED" => Ok("READ COMMITTED"),
+        "REPEATABLE READ" => Ok("REPEATABLE READ"),
+        "SERIALIZABLE" => Ok("SERIALIZABLE"),
+        _ => Err(AppError::other(format!("Unknown isolation level: {}", level))),
+    }
+}
+
+// Removes the query from the running map once execution finishes or the future is dropped
+struct RunningQueryGuard<'a> {
+    running: &'a RunningQueries,
+    query_id: String,
+}
+
+impl<'a> RunningQueryGuard<'a> {
+    fn register(running: &'a RunningQueries, query_id: &str, backend_pid: i32) -> Result<Self, AppError> {
+        let mut queries = running.lock()?;
+        // Replacing the entry would leave the earlier query impossible to cancel
+        if queries.contains_key(query_id) {
+            return Err(AppError::other(format!(
+                "A query with id \"{}\" is already running; cancel it or wait for it to finish",
+                query_id
+            )));
+        }
+        queries.insert(query_id.to_string(), RunningQuery { backend_pid, cancel_requested: false });
+        Ok(Self { running, query_id: query_id.to_string() })
+    }
+}
+
+fn cancel_requested(running: &RunningQueries, query_id: &str) -> bool {
+    running
+        .lock()
+        .map(|running| running.get(query_id).is_some_and(|q| q.cancel_requested))
+        .unwrap_or(false)
+}
+
+impl Drop for RunningQueryGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut running) = self.running.lock() {
+            running.remove(&self.query_id);
+        }
+    }
+}
+
+enum ExecItem {
+    Columns(Vec<String>),
+    Row(Vec<serde_json::Value>),
+    StatementDone { affected_rows: u64, notices: Vec<ServerNotice>, truncated: bool },
+}
+
+struct ExecOutcome {
+    affected_rows: u64,
+    cancelled: bool,
+    // Whether the statement still running when the loop ended lost rows to max_rows
+    truncated: bool,
+    // Notices raised after the last completed statement
+    notices: Vec<ServerNotice>,
+}
+
+fn infer_query_type(statement: &str) -> String {
+    classify_statement(statement).0
+}
+
+// Rebuilds the server's command tag, which sqlx only exposes as a row count
+fn command_tag(query_type: &str, statement: &str, affected_rows: u64) -> String {
+    match query_type {
+        "INSERT" => format!("INSERT 0 {}", affected_rows),
+        "SELECT" | "UPDATE" | "DELETE" | "MERGE" | "COPY" | "FETCH" | "MOVE" => {
+            format!("{} {}", query_type, affected_rows)
+        }
+        "CREATE" | "ALTER" | "DROP" => {
+            let object = statement
+                .split_whitespace()
+                .skip(1)
+                .map(|w| w.to_uppercase())
+                .find(|w| !matches!(w.as_str(), "OR" | "REPLACE" | "TEMP" | "TEMPORARY" | "UNLOGGED" | "UNIQUE"));
+            match object {
+                Some(object) => format!("{} {}", query_type, object),
+                None => query_type.to_string(),
+            }
+        }
+        _ => query_type.to_string(),
+    }
+}
+
+fn statement_result(
+    columns: Vec<String>,
+    rows: Vec<Vec<serde_json::Value>>,
+    affected_rows: u64,
+    duration_ms: u64,
+    notices: Vec<ServerNotice>,
+    cancelled: bool,
+) -> QueryResult {
+    QueryResult {
+        columns,
+        rows,
+        affected_rows,
+        query_type: String::new(),
+        command_tag: String::new(),
+        duration_ms,
+        span: None,
+        notices,
+        cancelled,
+        result_id: None,
+        has_more: false,
+        expanded: false,
+        truncated: false,
+    }
+}
+
+const DEFAULT_APPLICATION_NAME: &str = "pgmac";
+
+const DEFAULT_MAX_CONNECTIONS: u32 = 5;
+const DEFAULT_ACQUIRE_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 120;
+const DEFAULT_MAX_LIFETIME_SECS: u64 = 600;
+const DEFAULT_MAX_PINNED_SESSIONS: u32 = 10;
+
+fn pool_options(settings: &PoolSettings) -> Result<PgPoolOptions, AppError> {
+    let max_connections = settings.max_connections.unwrap_or(DEFAULT_MAX_CONNECTIONS);
+    let min_connections = settings.min_connections.unwrap_or(0);
+    let acquire_timeout = settings.acquire_timeout_secs.unwrap_or(DEFAULT_ACQUIRE_TIMEOUT_SECS);
+    if max_connections == 0 {
+        return Err(AppError::connection("The pool needs a maximum of at least one connection"));
+    }
+    if min_connections > max_connections {
+        return Err(AppError::connection(format!(
+            "The pool's minimum of {} connections is above its maximum of {}",
+            min_connections, max_connections
+        )));
+    }
+    if acquire_timeout == 0 {
+        return Err(AppError::connection("The pool's acquire timeout must be at least one second"));
+    }
+
+    let disabled_if_zero = |secs: u64| (secs > 0).then(|| Duration::from_secs(secs));
+    Ok(PgPoolOptions::new()
+        .max_connections(max_connections)
+        .min_connections(min_connections)
+        .acquire_timeout(Duration::from_secs(acquire_timeout))
+        .idle_timeout(disabled_if_zero(settings.idle_timeout_secs.unwrap_or(DEFAULT_IDLE_TIMEOUT_SECS)))
+        .max_lifetime(disabled_if_zero(settings.max_lifetime_secs.unwrap_or(DEFAULT_MAX_LIFETIME_SECS))))
+}
+
+// The session options as (setting, value) pairs for set_config. The role goes last, since
+// switching to a less privileged role first could forbid the other settings.
+fn session_settings(session: &SessionOptions) -> Vec<(String, String)> {
+    let mut settings: Vec<(String, String)> = session
+        .settings
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    settings.sort();
+
+    let named = [
+        ("search_path", session.search_path.clone()),
+        ("TimeZone", session.timezone.clone()),
+        ("statement_timeout", session.statement_timeout_ms.map(|ms| ms.to_string())),
+        ("role", session.role.clone()),
+    ];
+    settings.extend(named.into_iter().filter_map(|(name, value)| Some((name.to_string(), value?))));
+    settings
+}
+
+async fn apply_session_settings(conn: &mut PgConnection, settings: &[(String, String)]) -> Result<(), sqlx::Error> {
+    for (name, value) in settings {
+        sqlx::query("SELECT set_config($1, $2, false)")
+            .bind(name)
+            .bind(value)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+fn check_readable(what: &str, path: &str) -> Result<(), AppError> {
+    std::fs::File::open(path)
+        .map(|_| ())
+        .map_err(|e| AppError::connection(format!("Can't read the {} \"{}\": {}", what, path, e)))
+}
+
+// TLS failures come back from the driver as a bare handshake error; say which check failed
+fn connect_error(err: sqlx::Error, config: &DbConfig) -> AppError {
+    let sqlx::Error::Tls(source) = &err else {
+        return err.into();
+    };
+    let detail = source.to_string();
+    let lower = detail.to_lowercase();
+
+    let message = if lower.contains("not support tls") || lower.contains("not support ssl") {
+        format!(
+            "The server doesn't accept TLS connections, which sslmode \"{}\" requires",
+            config.ssl_mode.as_str()
+        )
+    } else if lower.contains("mismatch") || lower.contains("not valid for") {
+        format!(
+            "The server's certificate isn't valid for \"{}\" (sslmode verify-ca skips the host name check): {}",
+            config.host, detail
+        )
+    } else if lower.contains("certificate") || lower.contains("self signed") || lower.contains("issuer") {
+        let against = match &config.ssl_root_cert {
+            Some(path) => format!("the CA bundle \"{}\"", path),
+            None => "the system trust store".to_string(),
+        };
+        format!("The server's certificate couldn't be verified against {}: {}", against, detail)
+    } else {
+        format!("TLS negotiation failed: {}", detail)
+    };
+    AppError::connection(message)
+}
+
+// Built field by field so passwords and names never need escaping into a URL
+fn connect_options(config: &DbConfig) -> Result<PgConnectOptions, AppError> {
+    let mut options = PgConnectOptions::new_without_pgpass()
+        .host(&config.host)
+        .port(config.port)
+        .username(&config.user)
+        .database(&config.dbname);
+    if let Some(password) = &config.password {
+        options = options.password(password);
+    }
+    // Sent at startup so the name shows in pg_stat_activity from the first moment
+    options = options.application_name(
+        config.session.application_name.as_deref().unwrap_or(DEFAULT_APPLICATION_NAME),
+    );
+
+    // A host starting with `/` is the directory of a Unix socket, which TLS isn't used over.
+    // A tunnel's socket is different: it leads to the host, whose certificate TLS checks.
+    let ssl_mode = if config.host.starts_with('/') {
+        options = options.socket(&config.host);
+        SslMode::Disable
+    } else {
+        if let Some(dir) = &config.tunnel_socket {
+            options = options.socket(dir);
+        }
+        config.ssl_mode
+    };
+    options = options.ssl_mode(match ssl_mode {
+        SslMode::Disable => PgSslMode::Disable,
+        SslMode::Prefer => PgSslMode::Prefer,
+        SslMode::Require => PgSslMode::Require,
+        SslMode::VerifyCa => PgSslMode::VerifyCa,
+        SslMode::VerifyFull => PgSslMode::VerifyFull,
+    });
+    // Checked up front, since a missing file otherwise surfaces as a vague TLS failure
+    if let Some(path) = &config.ssl_root_cert {
+        check_readable("CA bundle", path)?;
+        options = options.ssl_root_cert(path);
+    }
+    match (&config.ssl_client_cert, &config.ssl_client_key) {
+        (Some(cert), Some(key)) => {
+            check_readable("client certificate", cert)?;
+            check_readable("client key", key)?;
+            options = options.ssl_client_cert(cert).ssl_client_key(key);
+        }
+        (None, None) => {}
+        _ => return Err(AppError::connection("A client certificate and its key must be given together")),
     }
+
+    for (key, value) in &config.parameters {
+        options = match key.as_str() {
+            // The server splits options on whitespace, so spaces inside values are escaped again
+            "options" => options.options(
+                parse_startup_options(value)?
+                    .into_iter()
+                    .map(|(name, value)| (name, value.replace('\\', "\\\\").replace(' ', "\\ "))),
+            ),
+            // Only `options` is kept when parsing; anything else has no sqlx equivalent
+            _ => options,
+        };
+    }
+    Ok(options)
+}
+
+fn is_query_canceled(err: &sqlx::Error) -> bool {
+    err.as_database_error()
+        .and_then(|e| e.code())
+        .is_some_and(|code| code == QUERY_CANCELED)
 }
 
 #[async_trait]
 impl DatabaseDriver for PostgresDriver {
-    async fn connect(&mut self, config: &DbConfig) -> Result<(), String> {
-        let connection_string = format!(
-            "postgres://{}:{}@{}:{}/{}",
-            config.user,
-            config.password.as_deref().unwrap_or(""),
-            config.host,
-            config.port,
-            config.dbname
-        );
+    async fn connect(&mut self, config: &DbConfig) -> Result<(), AppError> {
+        let options = connect_options(config)?;
+        let pool_options = pool_options(&self.pool_settings)?;
+        let settings = Arc::new(session_settings(&config.session));
+
+        // The pool retries a failing connection until it times out and then reports only the
+        // timeout, so the first connection is made directly to surface the actual error
+        let mut first = PgConnection::connect_with(&options)
+            .await
+            .map_err(|e| connect_error(e, config))?;
+        apply_session_settings(&mut first, &settings).await?;
+        let _ = first.close().await;
 
-        let pool = PgPoolOptions::new()
-            .max_connections(5)
-            .min_connections(0)
-            .acquire_timeout(Duration::from_secs(30))
-            .idle_timeout(Duration::from_secs(120))
-            .max_lifetime(Duration::from_secs(600))
+        let hook_settings = settings.clone();
+        let pool = pool_options
             .test_before_acquire(true)
-            .connect(&connection_string)
+            .after_connect(move |conn, _| {
+                let settings = hook_settings.clone();
+                Box::pin(async move { apply_session_settings(conn, &settings).await })
+            })
+            .connect_with(options.clone())
             .await
-            .map_err(|e| e.to_string())?;
+            .map_err(|e| connect_error(e, config))?;
 
         self.pool = Some(pool);
+        self.connect_options = Some(options);
+        self.session_settings = settings;
         Ok(())
     }
 
-    async fn query(&self, sql: &str) -> Result<QueryResult, String> {
-        let pool = self.pool.as_ref().ok_or("Not connected")?;
-        use futures::StreamExt;
-        use sqlx::Either;
+    fn set_default_limits(&mut self, limits: ExecutionLimits) {
+        self.default_limits = limits;
+    }
 
-        // Simple inference of query type
-        let trimmed_sql = sql.trim();
-        let query_type = trimmed_sql
-            .split_whitespace()
-            .next()
-            .map(|s| s.to_uppercase())
-            .unwrap_or_else(|| "UNKNOWN".to_string());
+    async fn query(&self, sql: &str, options: &QueryOptions) -> Result<Vec<QueryResult>, AppError> {
+        let spans = split_statements(sql);
 
-        let mut rows = Vec::new();
-        let mut affected_rows = 0;
-        let mut columns = Vec::new();
+        // Hold one connection throughout so its backend PID can be targeted by cancel_query
+        let mut conn = self.connection_for(options).await?;
+        let previous = apply_timeouts(&mut conn, &self.limits_for(options)).await?;
+        let results = self.statement_results(&mut conn, sql, options).await;
+        restore_timeouts(&mut conn, previous).await;
+
+        let mut results = results?;
+        let cancelled = results.last().is_some_and(|r| r.cancelled);
+
+        // Results line up with statements one-to-one unless the splitter disagrees with the server
+        let aligned = if cancelled {
+            results.len() <= spans.len()
+        } else {
+            results.len() == spans.len()
+        };
+
+        for (i, result) in results.iter_mut().enumerate() {
+            let statement = if aligned {
+                result.span = Some(spans[i].clone());
+                &sql[spans[i].start..spans[i].end]
+            } else {
+                sql
+            };
+            result.query_type = infer_query_type(statement);
+            result.command_tag = command_tag(&result.query_type, statement, result.affected_rows);
+        }
+
+        Ok(results)
+    }
+
+    async fn query_stream(
+        &self,
+        sql: &str,
+        options: &QueryOptions,
+        batch_size: usize,
+        on_event: &(dyn Fn(QueryStreamEvent) + Send + Sync),
+        local_meta: &LocalMetaCommands<'_>,
+    ) -> Result<QuerySummary, AppError> {
+        let started = Instant::now();
+        let query_id = options.query_id.as_deref().unwrap_or_default();
+        let batch_size = batch_size.max(1);
 
-        let mut stream = sqlx::query(sql).fetch_many(pool);
+        let mut batch = Vec::with_capacity(batch_size);
+        let mut rows_received: u64 = 0;
+        let mut statement_index = 0;
 
-        while let Some(result) = stream.next().await {
-            match result.map_err(|e| e.to_string())? {
-                Either::Left(res) => {
-                    affected_rows += res.rows_affected();
+        let mut truncated = false;
+
+        let mut on_item = |item: ExecItem| match item {
+            ExecItem::Columns(columns) => on_event(QueryStreamEvent::Columns {
+                query_id: query_id.to_string(),
+                statement_index,
+                columns,
+            }),
+            ExecItem::Row(row) => {
+                batch.push(row);
+                rows_received += 1;
+                if batch.len() >= batch_size {
+                    on_event(QueryStreamEvent::Rows {
+                        query_id: query_id.to_string(),
+                        statement_index,
+                        rows: std::mem::replace(&mut batch, Vec::with_capacity(batch_size)),
+                        rows_received,
+                    });
+                }
+            }
+            ExecItem::StatementDone { notices, truncated: statement_truncated, .. } => {
+                truncated |= statement_truncated;
+                // Flush so a batch never mixes rows from different result sets
+                if !batch.is_empty() {
+                    on_event(QueryStreamEvent::Rows {
+                        query_id: query_id.to_string(),
+                        statement_index,
+                        rows: std::mem::replace(&mut batch, Vec::with_capacity(batch_size)),
+                        rows_received,
+                    });
+                }
+                if !notices.is_empty() {
+                    on_event(QueryStreamEvent::Notices {
+                        query_id: query_id.to_string(),
+                        statement_index,
+                        notices,
+                    });
                 }
-                Either::Right(row) => {
-                    if columns.is_empty() {
-                        columns = row.columns().iter().map(|c| c.name().to_string()).collect();
+                statement_index += 1;
+            }
+        };
+
+        let mut conn = self.connection_for(options).await?;
+        let previous = apply_timeouts(&mut conn, &self.limits_for(options)).await?;
+        let outcome = async {
+            let mut outcome = ExecOutcome { affected_rows: 0, cancelled: false, truncated: false, notices: Vec::new() };
+            // Backslash commands run in turn with the SQL around them, their results streamed
+            // like any other statement's
+            for part in split_meta_commands(sql) {
+                match part {
+                    ScriptPart::Sql(span) => {
+                        let part = self
+                            .execute(&mut conn, &sql[span.start..span.end], options, &mut on_item)
+                            .await
+                            .map_err(|e| e.map_position(|position| position + sql[..span.start].chars().count()))?;
+                        outcome.affected_rows += part.affected_rows;
+                        outcome.truncated = part.truncated;
+                        outcome.notices.extend(part.notices);
+                        if part.cancelled {
+                            outcome.cancelled = true;
+                            break;
+                        }
                     }
-                    
-                    let mut row_values = Vec::new();
-                    for (i, _) in row.columns().iter().enumerate() {
-                         let value = map_postgres_value(&row, i);
-                         row_values.push(value);
+                    ScriptPart::Meta(span) => {
+                        let text = &sql[span.start..span.end];
+                        for result in self.inline_meta_command(&mut conn, text, &span, options, local_meta).await? {
+                            on_item(ExecItem::Columns(result.columns));
+                            for row in result.rows {
+                                on_item(ExecItem::Row(row));
+                            }
+                            on_item(ExecItem::StatementDone {
+                                affected_rows: 0,
+                                notices: result.notices,
+                                truncated: result.truncated,
+                            });
+                        }
                     }
-                    rows.push(row_values);
                 }
             }
+            Ok::<_, AppError>(outcome)
         }
+        .await;
+        restore_timeouts(&mut conn, previous).await;
+        let outcome = outcome?;
 
-        Ok(QueryResult {
-            columns,
-            rows,
-            affected_rows,
-            query_type,
+        if !batch.is_empty() {
+            on_event(QueryStreamEvent::Rows {
+                query_id: query_id.to_string(),
+                statement_index,
+                rows: batch,
+                rows_received,
+            });
+        }
+
+        if !outcome.notices.is_empty() {
+            on_event(QueryStreamEvent::Notices {
+                query_id: query_id.to_string(),
+                statement_index,
+                notices: outcome.notices,
+            });
+        }
+
+        let summary = QuerySummary {
+            total_rows: rows_received,
+            statement_count: statement_index,
+            affected_rows: outcome.affected_rows,
+            // The last statement's, as that is what the client ends up looking at
+            query_type: split_statements(sql)
+                .last()
+                .map(|span| infer_query_type(&sql[span.start..span.end]))
+                .unwrap_or_else(|| "UNKNOWN".to_string()),
+            cancelled: outcome.cancelled,
+            truncated: truncated || outcome.truncated,
+            duration_ms: started.elapsed().as_millis() as u64,
+        };
+
+        on_event(QueryStreamEvent::Done {
+            query_id: query_id.to_string(),
+            summary: summary.clone(),
+        });
+
+        Ok(summary)
+    }
+
+    async fn run_script(
+        &self,
+        sql: &str,
+        options: &QueryOptions,
+        policy: ErrorPolicy,
+        local_meta: &LocalMetaCommands<'_>,
+    ) -> Result<ScriptReport, AppError> {
+        let started = Instant::now();
+        // One connection for the whole script so the wrapping transaction and session state carry over
+        let mut conn = self.connection_for(options).await?;
+
+        let scope = match policy {
+            ErrorPolicy::Rollback => match ScriptScope::open(&mut conn).await? {
+                Some(scope) => Some(scope),
+                None => {
+                    return Err(AppError::other("The current transaction is aborted; roll it back before running the script"));
+                }
+            },
+            _ => None,
+        };
+        let previous = apply_timeouts(&mut conn, &self.limits_for(options)).await?;
+
+        let mut statements = Vec::new();
+        let mut halted = false;
+
+        for (index, info) in classify_statements(sql).into_iter().enumerate() {
+            let text = &sql[info.span.start..info.span.end];
+            let meta = info.kind == StatementKind::Meta;
+            let mut report = StatementReport {
+                index,
+                span: info.span.clone(),
+                command: info.command,
+                status: StatementStatus::Skipped,
+                affected_rows: 0,
+                duration_ms: 0,
+                result: None,
+                error: None,
+            };
+
+            if halted {
+                statements.push(report);
+                continue;
+            }
+
+            let statement_started = Instant::now();
+            let outcome = if meta {
+                // A report holds one result, so `\d` matching several relations shows the first
+                self.inline_meta_command(&mut conn, text, &info.span, options, local_meta)
+                    .await
+                    .map(|results| results.into_iter().take(1).collect())
+            } else {
+                self.statement_results(&mut conn, text, options).await
+            };
+            report.duration_ms = statement_started.elapsed().as_millis() as u64;
+
+            match outcome {
+                Ok(results) => {
+                    // A single statement yields a single result, but keep the last to be safe
+                    let mut result = results
+                        .into_iter()
+                        .last()
+                        .unwrap_or_else(|| statement_result(Vec::new(), Vec::new(), 0, report.duration_ms, Vec::new(), false));
+                    result.span = Some(info.span);
+                    // Meta-commands keep the labels they were answered with
+                    if !meta {
+                        result.query_type = report.command.clone();
+                        result.command_tag = command_tag(&result.query_type, text, result.affected_rows);
+                    }
+
+                    report.affected_rows = result.affected_rows;
+                    report.status = if result.cancelled { StatementStatus::Cancelled } else { StatementStatus::Succeeded };
+                    halted = result.cancelled;
+                    report.result = Some(result);
+                }
+                Err(e) => {
+                    report.status = StatementStatus::Failed;
+                    report.error = Some(e);
+                    halted = policy != ErrorPolicy::Continue;
+                }
+            }
+            statements.push(report);
+        }
+
+        let failed = statements
+            .iter()
+            .any(|s| matches!(s.status, StatementStatus::Failed | StatementStatus::Cancelled));
+
+        let mut rolled_back = false;
+        let mut error = None;
+        if let Some(finish) = scope.as_ref().map(|scope| scope.close_sql(failed)) {
+            match execute_tracked(&mut conn, &finish).await {
+                Ok(()) => rolled_back = failed,
+                // A failed COMMIT (e.g. a deferred constraint) has rolled everything back too
+                Err(e) => {
+                    rolled_back = matches!(scope, Some(ScriptScope::Transaction));
+                    error = Some(e);
+                }
+            }
+        }
+
+        restore_timeouts(&mut conn, previous).await;
+
+        if rolled_back {
+            for statement in &mut statements {
+                if statement.status == StatementStatus::Succeeded {
+                    statement.status = StatementStatus::RolledBack;
+                }
+            }
+        }
+
+        let count = |status: StatementStatus| statements.iter().filter(|s| s.status == status).count();
+        Ok(ScriptReport {
+            policy,
+            succeeded: count(StatementStatus::Succeeded),
+            failed: count(StatementStatus::Failed),
+            skipped: count(StatementStatus::Skipped),
+            rolled_back,
+            error,
+            duration_ms: started.elapsed().as_millis() as u64,
+            statements,
         })
     }
 
-    async fn get_schemas(&self) -> Result<Vec<String>, String> {
-        let pool = self.pool.as_ref().ok_or("Not connected")?;
+    async fn describe(&self, sql: &str, options: &QueryOptions) -> Result<StatementDescription, AppError> {
+        // The tab's connection, so temp tables and search_path resolve as they would when run
+        let mut conn = self.connection_for(options).await?;
+        let described = conn.describe(sql).await;
+        // A statement that fails to parse aborts an open transaction as running it would
+        conn.track(sql, 0, described.is_err());
+        let described = described?;
+
+        let parameters = match described.parameters() {
+            Some(Either::Left(types)) => types.iter().map(|t| t.name().to_string()).collect(),
+            Some(Either::Right(count)) => vec!["UNKNOWN".to_string(); count],
+            None => Vec::new(),
+        };
+
+        let columns = described
+            .columns()
+            .iter()
+            .map(|c| ResultColumn {
+                name: c.name().to_string(),
+                data_type: c.type_info().name().to_string(),
+            })
+            .collect();
+
+        Ok(StatementDescription { parameters, columns })
+    }
+
+    async fn explain(&self, sql: &str, options: &QueryOptions, explain: ExplainOptions) -> Result<QueryPlan, AppError> {
+        let spans = split_statements(sql);
+        let (body, span_start) = match spans.as_slice() {
+            [span] => (&sql[span.start..span.end], span.start),
+            _ => return Err(AppError::other("EXPLAIN takes exactly one statement")),
+        };
+
+        let (_, kind) = classify_statement(body);
+        if kind == StatementKind::Meta {
+            return Err(AppError::other("psql meta-commands can't be explained"));
+        }
+
+        let prefix = format!(
+            "EXPLAIN (FORMAT JSON, ANALYZE {}, BUFFERS {}, VERBOSE {}) ",
+            explain.analyze, explain.buffers, explain.verbose
+        );
+        let explain_sql = format!("{}{}", prefix, body);
+        // ANALYZE executes the statement, and even a query can write through a writable CTE or a
+        // volatile function, so every analyzed statement is undone afterwards
+        let rollback = explain.analyze;
+
+        let mut conn = self.connection_for(options).await?;
+        let previous = apply_timeouts(&mut conn, &self.limits_for(options)).await?;
+        let plan = self.explain_plan(&mut conn, &explain_sql, rollback, options).await;
+        restore_timeouts(&mut conn, previous).await;
+
+        // Error positions count from the start of the EXPLAIN; report them against `sql`, with
+        // anything pointing into the prefix clamped to 0
+        let (prefix_len, leading) = (prefix.chars().count(), sql[..span_start].chars().count());
+        let (raw, rolled_back) = plan.map_err(|e| {
+            e.map_position(|position| match position.saturating_sub(prefix_len) {
+                0 => 0,
+                position => position + leading,
+            })
+        })?;
+        parse_plan(raw, explain.analyze, rolled_back)
+    }
+
+    async fn meta_command(&self, command: &MetaCommand, options: &QueryOptions) -> Result<Vec<QueryResult>, AppError> {
+        let mut conn = self.connection_for(options).await?;
+        let previous = apply_timeouts(&mut conn, &self.limits_for(options)).await?;
+        let results = self.meta_results(&mut conn, command, options).await;
+        restore_timeouts(&mut conn, previous).await;
+        results
+    }
+
+    async fn cancel_query(&self, query_id: &str) -> Result<bool, AppError> {
+        let options = self.connect_options.as_ref().ok_or_else(|| AppError::connection("Not connected"))?;
+
+        let backend_pid = {
+            let mut running = self.running.lock()?;
+            match running.get_mut(query_id) {
+                Some(query) => {
+                    query.cancel_requested = true;
+                    query.backend_pid
+                }
+                None => return Ok(false),
+            }
+        };
+
+        // Sent on a connection of its own: the pool may be saturated by the very queries being
+        // cancelled, and waiting on it would hold the cancel up for the whole acquire timeout.
+        // It also skips the session options, as a configured role usually may not signal the
+        // backends of the user who logged in.
+        let mut conn = tokio::time::timeout(CANCEL_CONNECT_TIMEOUT, PgConnection::connect_with(options))
+            .await
+            .map_err(|_| AppError::connection("Timed out connecting to send the cancel request"))??;
+        let cancelled = sqlx::query_scalar("SELECT pg_cancel_backend($1)")
+            .bind(backend_pid)
+            .fetch_one(&mut conn)
+            .await;
+        let _ = conn.close().await;
+
+        Ok(cancelled?)
+    }
+
+    async fn begin_transaction(
+        &self,
+        session_id: &str,
+        isolation_level: Option<String>,
+        read_only: bool,
+    ) -> Result<TransactionStatus, AppError> {
+        let mut begin = String::from("BEGIN");
+        if let Some(level) = isolation_level.as_deref() {
+            begin.push_str(" ISOLATION LEVEL ");
+            begin.push_str(isolation_level_sql(level)?);
+        }
+        if read_only {
+            begin.push_str(" READ ONLY");
+        }
+
+        let conn = self.pin_connection(session_id, true).await?;
+        let mut conn = SessionConnection::Pinned(conn.lock_owned().await);
+        execute_tracked(&mut conn, &begin).await?;
+        Ok(conn.status())
+    }
+
+    async fn commit(&self, session_id: &str) -> Result<TransactionStatus, AppError> {
+        self.end_transaction(session_id, "COMMIT").await
+    }
+
+    async fn rollback(&self, session_id: &str) -> Result<TransactionStatus, AppError> {
+        self.end_transaction(session_id, "ROLLBACK").await
+    }
+
+    async fn transaction_status(&self, session_id: &str) -> Result<TransactionStatus, AppError> {
+        match self.pinned_connection(session_id)? {
+            Some(conn) => Ok(conn.lock().await.status),
+            None => Ok(TransactionStatus::Idle),
+        }
+    }
+
+    async fn pin_session(&self, session_id: &str) -> Result<(), AppError> {
+        self.pin_connection(session_id, false).await?;
+        Ok(())
+    }
+
+    async fn reset_session(&self, session_id: &str) -> Result<(), AppError> {
+        let conn = self
+            .pinned_connection(session_id)?
+            .ok_or_else(|| AppError::not_found("No pinned session for this tab"))?;
+        let mut conn = SessionConnection::Pinned(conn.lock_owned().await);
+
+        // DISCARD ALL refuses to run inside a transaction block
+        if conn.status() != TransactionStatus::Idle {
+            execute_tracked(&mut conn, "ROLLBACK").await?;
+        }
+        execute_simple(&mut conn, "DISCARD ALL").await?;
+        // The server's prepared statements are gone, so sqlx's cache of them has to go too
+        conn.clear_cached_statements().await?;
+        // DISCARD ALL also resets the connection's session options
+        Ok(apply_session_settings(&mut conn, &self.session_settings).await?)
+    }
+
+    async fn release_session(&self, session_id: &str) -> Result<(), AppError> {
+        self.unpin_connection(session_id).await
+    }
+
+    fn has_session(&self, session_id: &str) -> bool {
+        self.sessions
+            .lock()
+            .map(|sessions| sessions.contains_key(session_id))
+            .unwrap_or(false)
+    }
+
+    fn pool_status(&self) -> Result<PoolStatus, AppError> {
+        let pool = self.pool.as_ref().ok_or_else(|| AppError::connection("Not connected"))?;
+        let size = pool.size();
+        let idle = pool.num_idle() as u32;
+        Ok(PoolStatus {
+            size,
+            idle,
+            in_use: size.saturating_sub(idle),
+            max_connections: pool.options().get_max_connections(),
+            pinned: self.sessions.lock()?.len() as u32,
+            max_pinned: self.max_pinned_sessions(),
+        })
+    }
+
+    async fn open_cursor(&self, sql: &str, options: &QueryOptions) -> Result<Option<Box<dyn QueryCursor>>, AppError> {
+        let pool = self.pool.as_ref().ok_or_else(|| AppError::connection("Not connected"))?;
+
+        let spans = split_statements(sql);
+        let span = match spans.as_slice() {
+            [span] => span.clone(),
+            _ => return Ok(None),
+        };
+
+        let body = &sql[span.start..span.end];
+        // A cursor can't be declared over a writable CTE, which classifies as DML
+        let (query_type, kind) = classify_statement(body);
+        if kind != StatementKind::Query || !matches!(query_type.as_str(), "SELECT" | "VALUES" | "TABLE") {
+            return Ok(None);
+        }
+
+        let name = format!("pgmac_cursor_{}", uuid::Uuid::new_v4().simple());
+
+        // Cursors only live as long as their transaction, so it stays open until the cursor is closed
+        let declare = format!("DECLARE {} NO SCROLL CURSOR FOR {}", name, body);
+        let args = bind_params(&options.params)?.unwrap_or_default();
+        let mut tx = pool.begin().await?;
+
+        // Local to the cursor's transaction, so every page fetch runs under the same limits
+        let limits = self.limits_for(options);
+        if let Some(ms) = limits.statement_timeout_ms {
+            set_local(&mut tx, "statement_timeout", &ms.to_string()).await?;
+        }
+        if let Some(ms) = limits.lock_timeout_ms {
+            set_local(&mut tx, "lock_timeout", &ms.to_string()).await?;
+        }
+        sqlx::query_with(&declare, args)
+            .persistent(false)
+            .execute(&mut *tx)
+            .await?;
+
+        // Page fetches register under the query id too, so cancel_query reaches them
+        let backend_pid = match options.query_id {
+            Some(_) => sqlx::query_scalar("SELECT pg_backend_pid()").fetch_one(&mut *tx).await?,
+            None => 0,
+        };
+
+        Ok(Some(Box::new(PostgresCursor {
+            tx: Some(tx),
+            name,
+            query_type,
+            span,
+            running: self.running.clone(),
+            query_id: options.query_id.clone(),
+            backend_pid,
+        })))
+    }
+
+    async fn get_schemas(&self) -> Result<Vec<String>, AppError> {
+        let pool = self.pool.as_ref().ok_or_else(|| AppError::connection("Not connected"))?;
         let rows = sqlx::query(
             "SELECT schema_name FROM information_schema.schemata \
              WHERE schema_name NOT LIKE 'pg_%' \
@@ -100,27 +1770,25 @@ impl DatabaseDriver for PostgresDriver {
              ORDER BY schema_name"
         )
             .fetch_all(pool)
-            .await
-            .map_err(|e| e.to_string())?;
+            .await?;
 
         let schemas: Vec<String> = rows.iter().map(|row| row.get("schema_name")).collect();
         Ok(schemas)
     }
 
-    async fn get_tables(&self, schema: &str) -> Result<Vec<String>, String> {
-        let pool = self.pool.as_ref().ok_or("Not connected")?;
+    async fn get_tables(&self, schema: &str) -> Result<Vec<String>, AppError> {
+        let pool = self.pool.as_ref().ok_or_else(|| AppError::connection("Not connected"))?;
         let rows = sqlx::query("SELECT table_name FROM information_schema.tables WHERE table_schema = $1 ORDER BY table_name")
             .bind(schema)
             .fetch_all(pool)
-            .await
-            .map_err(|e| e.to_string())?;
+            .await?;
 
         let tables: Vec<String> = rows.iter().map(|row| row.get("table_name")).collect();
         Ok(tables)
     }
 
-    async fn get_columns(&self, schema: &str, table: &str) -> Result<Vec<crate::models::ColumnDefinition>, String> {
-        let pool = self.pool.as_ref().ok_or("Not connected")?;
+    async fn get_columns(&self, schema: &str, table: &str) -> Result<Vec<crate::models::ColumnDefinition>, AppError> {
+        let pool = self.pool.as_ref().ok_or_else(|| AppError::connection("Not connected"))?;
         
         let sql = r#"
             SELECT 
@@ -156,8 +1824,7 @@ impl DatabaseDriver for PostgresDriver {
             .bind(schema)
             .bind(table)
             .fetch_all(pool)
-            .await
-            .map_err(|e| e.to_string())?;
+            .await?;
 
         let cols: Vec<crate::models::ColumnDefinition> = rows.iter().map(|row| {
              let name: String = row.get("column_name");
@@ -177,12 +1844,11 @@ impl DatabaseDriver for PostgresDriver {
         Ok(cols)
     }
 
-    async fn ping(&self) -> Result<(), String> {
-        let pool = self.pool.as_ref().ok_or("Not connected")?;
+    async fn ping(&self) -> Result<(), AppError> {
+        let pool = self.pool.as_ref().ok_or_else(|| AppError::connection("Not connected"))?;
         sqlx::query("SELECT 1")
             .execute(pool)
-            .await
-            .map_err(|e| e.to_string())?;
+            .await?;
         Ok(())
     }
 
@@ -194,8 +1860,8 @@ impl DatabaseDriver for PostgresDriver {
         col_type: Option<String>,
         new_value: Option<String>, 
         row_identifiers: Vec<(String, Option<String>, String)>
-    ) -> Result<u64, String> {
-        let pool = self.pool.as_ref().ok_or("Not connected")?;
+    ) -> Result<u64, AppError> {
+        let pool = self.pool.as_ref().ok_or_else(|| AppError::connection("Not connected"))?;
         
         // Construct SQL
         // UPDATE "schema"."table" SET "col" = $1::type WHERE ...
@@ -263,11 +1929,88 @@ impl DatabaseDriver for PostgresDriver {
              }
         }
         
-        let result = query.execute(pool).await.map_err(|e| e.to_string())?;
+        let result = query.execute(pool).await?;
         Ok(result.rows_affected())
     }
 }
 
+pub struct PostgresCursor {
+    tx: Option<Transaction<'static, Postgres>>,
+    name: String,
+    query_type: String,
+    span: StatementSpan,
+    running: Arc<RunningQueries>,
+    query_id: Option<String>,
+    backend_pid: i32,
+}
+
+#[async_trait]
+impl QueryCursor for PostgresCursor {
+    async fn fetch_page(&mut self, page_size: usize) -> Result<QueryResult, AppError> {
+        let tx = self.tx.as_mut().ok_or_else(|| AppError::other("Cursor is closed"))?;
+        let page_size = page_size.max(1);
+        let started = Instant::now();
+
+        let _guard = match &self.query_id {
+            Some(id) => Some(RunningQueryGuard::register(&self.running, id, self.backend_pid)?),
+            None => None,
+        };
+
+        let notices = NoticeBuffer::default();
+        let fetch = format!("FETCH FORWARD {} FROM {}", page_size, self.name);
+        let (fetched, cancelled) = match collect_notices(
+            notices.clone(),
+            sqlx::query(&fetch).persistent(false).fetch_all(&mut **tx),
+        )
+        .await
+        {
+            Ok(fetched) => (fetched, false),
+            Err(e) if is_query_canceled(&e) && self.query_id.as_deref().is_some_and(|id| cancel_requested(&self.running, id)) => {
+                (Vec::new(), true)
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let columns = fetched
+            .first()
+            .map(|row| row.columns().iter().map(|c| c.name().to_string()).collect())
+            .unwrap_or_default();
+
+        let rows: Vec<Vec<serde_json::Value>> = fetched
+            .iter()
+            .map(|row| (0..row.len()).map(|i| map_postgres_value(row, i)).collect())
+            .collect();
+
+        // A full page means there may be more; the next fetch returns empty if there is not.
+        // A cancelled fetch aborts the cursor's transaction, so the result set ends there.
+        let has_more = !cancelled && rows.len() == page_size;
+
+        Ok(QueryResult {
+            command_tag: format!("FETCH {}", rows.len()),
+            columns,
+            rows,
+            affected_rows: 0,
+            query_type: self.query_type.clone(),
+            duration_ms: started.elapsed().as_millis() as u64,
+            span: Some(self.span.clone()),
+            notices: drain_notices(&notices),
+            cancelled,
+            result_id: None,
+            has_more,
+            expanded: false,
+            truncated: false,
+        })
+    }
+
+    async fn close(&mut self) -> Result<(), AppError> {
+        if let Some(tx) = self.tx.take() {
+            // Ending the transaction releases the cursor as well
+            tx.rollback().await?;
+        }
+        Ok(())
+    }
+}
+
 fn map_postgres_value(row: &PgRow, index: usize) -> serde_json::Value {
     use sqlx::ValueRef;
     let value_ref = match row.try_get_raw(index) {
@@ -361,6 +2104,13 @@ fn map_postgres_value(row: &PgRow, index: usize) -> serde_json::Value {
                  serde_json::Value::Null
              }
         },
+        // The simple query protocol returns text, which is already in display form
+        "BYTEA" | "MONEY" if value_ref.format() == PgValueFormat::Text => {
+             match value_ref.as_str() {
+                 Ok(s) => serde_json::Value::String(s.to_string()),
+                 Err(_) => serde_json::Value::Null,
+             }
+        },
         "BYTEA" => {
              // Display binary data as hex string with \x prefix (PostgreSQL style)
              if let Ok(bytes) = value_ref.as_bytes() {
@@ -0,0 +1,463 @@
+use crate::models::{StatementInfo, StatementKind, StatementSpan, TransactionStatus};
+
+// Splits a script into statements on top-level semicolons, skipping over quoted
+// text, comments and dollar-quoted bodies. Spans cover the statement text without
+// surrounding whitespace, comments or the terminating semicolon. A psql backslash
+// command runs to the end of its line and comes back as a statement of its own.
+pub fn split_statements(sql: &str) -> Vec<StatementSpan> {
+    let bytes = sql.as_bytes();
+    let mut spans = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut end = 0;
+    let mut i = 0;
+    // Like psql, semicolons inside a `BEGIN ATOMIC ... END` body don't end the statement.
+    // A BEGIN or CASE opens a block unless it's the statement's first word, which keeps
+    // transaction BEGINs out, and END closes one.
+    let mut words = 0;
+    let mut block_depth: usize = 0;
+
+    while i < bytes.len() {
+        let token_start = i;
+
+        if let Some(comment_end) = skip_comment(bytes, i) {
+            i = comment_end;
+            continue;
+        }
+
+        match bytes[i] {
+            b';' if block_depth == 0 => {
+                if let Some(s) = start.take() {
+                    spans.push(StatementSpan { start: s, end });
+                }
+                words = 0;
+                i += 1;
+                continue;
+            }
+            b'\\' => {
+                if let Some(s) = start.take() {
+                    spans.push(StatementSpan { start: s, end });
+                }
+                (words, block_depth) = (0, 0);
+                let line_end = bytes[i..].iter().position(|&b| b == b'\n').map_or(bytes.len(), |pos| i + pos);
+                spans.push(StatementSpan { start: i, end: sql[..line_end].trim_end().len() });
+                i = line_end;
+                continue;
+            }
+            b if b.is_ascii_whitespace() => {
+                i += 1;
+                continue;
+            }
+            b if is_ident_byte(b) => {
+                while i < bytes.len() && is_ident_byte(bytes[i]) {
+                    i += 1;
+                }
+                words += 1;
+                let word = &sql[token_start..i];
+                if (word.eq_ignore_ascii_case("begin") || word.eq_ignore_ascii_case("case")) && words > 1 {
+                    block_depth += 1;
+                } else if word.eq_ignore_ascii_case("end") {
+                    block_depth = block_depth.saturating_sub(1);
+                }
+            }
+            _ => i = skip_literal(bytes, i).unwrap_or(i + 1),
+        }
+
+        start.get_or_insert(token_start);
+        end = i;
+    }
+
+    if let Some(s) = start {
+        spans.push(StatementSpan { start: s, end });
+    }
+
+    spans
+}
+
+// Statements with the command each one runs and its broad kind
+pub fn classify_statements(sql: &str) -> Vec<StatementInfo> {
+    split_statements(sql)
+        .into_iter()
+        .map(|span| {
+            let (command, kind) = classify_statement(&sql[span.start..span.end]);
+            StatementInfo { span, command, kind }
+        })
+        .collect()
+}
+
+// The statement's command keyword, looking past a WITH clause to the statement it
+// introduces (e.g. `WITH x AS (...) DELETE ...` is a DELETE)
+pub fn classify_statement(statement: &str) -> (String, StatementKind) {
+    if statement.starts_with('\\') {
+        let command = statement.split_whitespace().next().unwrap_or("\\");
+        return (command.to_string(), StatementKind::Meta);
+    }
+
+    let mut words = keywords(statement);
+    // Set for a WITH whose CTEs modify data, which makes even a SELECT a write
+    let mut writes = false;
+    let command = match words.next() {
+        Some((first, _)) if first == "WITH" => {
+            let mut previous = (first.clone(), 0);
+            words
+                .find(|(word, depth)| {
+                    // A CTE's body is the parenthesized statement after `AS` or `[NOT] MATERIALIZED`,
+                    // at any depth since a CTE can have a WITH of its own
+                    if *depth == previous.1 + 1 && matches!(previous.0.as_str(), "AS" | "MATERIALIZED") {
+                        writes |= matches!(word.as_str(), "INSERT" | "UPDATE" | "DELETE" | "MERGE");
+                    }
+                    previous = (word.clone(), *depth);
+                    *depth == 0
+                        && matches!(word.as_str(), "SELECT" | "INSERT" | "UPDATE" | "DELETE" | "MERGE" | "VALUES" | "TABLE")
+                })
+                .map(|(word, _)| word)
+                .unwrap_or(first)
+        }
+        Some((first, _)) => first,
+        None => "UNKNOWN".to_string(),
+    };
+
+    let kind = match command.as_str() {
+        "SELECT" | "VALUES" | "TABLE" if writes => StatementKind::Dml,
+        "SELECT" | "VALUES" | "TABLE" | "SHOW" | "EXPLAIN" | "FETCH" => StatementKind::Query,
+        "INSERT" | "UPDATE" | "DELETE" | "MERGE" | "COPY" => StatementKind::Dml,
+        "CREATE" | "ALTER" | "DROP" | "TRUNCATE" | "COMMENT" | "GRANT" | "REVOKE" | "SECURITY" | "IMPORT" => {
+            StatementKind::Ddl
+        }
+        "BEGIN" | "START" | "COMMIT" | "END" | "ROLLBACK" | "ABORT" | "SAVEPOINT" | "RELEASE" => {
+            StatementKind::Transaction
+        }
+        "SET" | "RESET" | "DISCARD" | "LISTEN" | "UNLISTEN" | "LOAD" => StatementKind::Session,
+        _ => StatementKind::Other,
+    };
+
+    (command, kind)
+}
+
+// The transaction status a statement leaves the session in, given the one it ran in and
+// whether it succeeded
+pub fn transaction_status_after(status: TransactionStatus, statement: &str, succeeded: bool) -> TransactionStatus {
+    let words: Vec<String> = keywords(statement).map(|(word, _)| word).take(5).collect();
+    let word = |i: usize| words.get(i).map_or("", String::as_str);
+
+    if !succeeded {
+        // A failed COMMIT (e.g. on a deferred constraint) has rolled the transaction back;
+        // anything else failing or being cancelled aborts it
+        let commit = matches!(word(0), "COMMIT" | "END") && word(1) != "PREPARED";
+        return match status {
+            TransactionStatus::Idle => TransactionStatus::Idle,
+            TransactionStatus::Active if commit || (word(0), word(1)) == ("PREPARE", "TRANSACTION") => {
+                TransactionStatus::Idle
+            }
+            _ => TransactionStatus::Aborted,
+        };
+    }
+
+    let chain = words.windows(2).any(|pair| pair == ["AND", "CHAIN"]);
+    match (word(0), word(1)) {
+        ("BEGIN" | "START", _) => TransactionStatus::Active,
+        // Only allowed outside a transaction block, which they leave as it is
+        ("COMMIT" | "ROLLBACK", "PREPARED") => status,
+        // `ROLLBACK [WORK | TRANSACTION] TO [SAVEPOINT] name` recovers an aborted transaction
+        ("ROLLBACK", _) if word(1) == "TO" || word(2) == "TO" => TransactionStatus::Active,
+        ("COMMIT" | "END" | "ROLLBACK" | "ABORT", _) if chain => TransactionStatus::Active,
+        ("COMMIT" | "END" | "ROLLBACK" | "ABORT", _) | ("PREPARE", "TRANSACTION") => TransactionStatus::Idle,
+        _ => status,
+    }
+}
+
+// Upper-cased bare words outside quotes and comments, with their parenthesis depth
+fn keywords(statement: &str) -> impl Iterator<Item = (String, usize)> + '_ {
+    let bytes = statement.as_bytes();
+    let mut depth: usize = 0;
+    let mut i = 0;
+
+    std::iter::from_fn(move || {
+        while i < bytes.len() {
+            if let Some(end) = skip_comment(bytes, i).or_else(|| skip_literal(bytes, i)) {
+                i = end;
+                continue;
+            }
+
+            match bytes[i] {
+                b'(' => depth += 1,
+                b')' => depth = depth.saturating_sub(1),
+                b if is_ident_byte(b) => {
+                    let start = i;
+                    while i < bytes.len() && is_ident_byte(bytes[i]) {
+                        i += 1;
+                    }
+                    return Some((statement[start..i].to_uppercase(), depth));
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        None
+    })
+}
+
+// Index of the statement containing byte `offset`; between statements, the one before it
+pub fn statement_at(spans: &[StatementSpan], offset: usize) -> Option<usize> {
+    spans
+        .iter()
+        .rposition(|span| span.start <= offset)
+        .or((!spans.is_empty()).then_some(0))
+}
+
+// End of the comment starting at `i`, if one does
+pub(super) fn skip_comment(bytes: &[u8], i: usize) -> Option<usize> {
+    match (bytes[i], bytes.get(i + 1)) {
+        (b'-', Some(b'-')) => Some(skip_line_comment(bytes, i)),
+        (b'/', Some(b'*')) => Some(skip_block_comment(bytes, i)),
+        _ => None,
+    }
+}
+
+// End of the string literal, quoted identifier or dollar-quoted body starting at `i`, if one does
+pub(super) fn skip_literal(bytes: &[u8], i: usize) -> Option<usize> {
+    match bytes[i] {
+        b'\'' if is_escape_string(bytes, i) => Some(skip_escaped_quoted(bytes, i)),
+        b'\'' | b'"' => Some(skip_quoted(bytes, i)),
+        b'$' => dollar_tag_len(bytes, i).map(|tag_len| skip_dollar_quoted(bytes, i, tag_len)),
+        _ => None,
+    }
+}
+
+fn skip_quoted(bytes: &[u8], open: usize) -> usize {
+    let quote = bytes[open];
+    // A doubled quote closes and immediately reopens, which scans the same way
+    match bytes[open + 1..].iter().position(|&b| b == quote) {
+        Some(pos) => open + 1 + pos + 1,
+        None => bytes.len(),
+    }
+}
+
+// `E'...'`, where the quote follows a standalone E
+fn is_escape_string(bytes: &[u8], quote: usize) -> bool {
+    quote > 0
+        && matches!(bytes[quote - 1], b'e' | b'E')
+        && (quote < 2 || !is_ident_byte(bytes[quote - 2]))
+}
+
+// Inside an E-string a backslash escapes the next byte, quotes included
+fn skip_escaped_quoted(bytes: &[u8], open: usize) -> usize {
+    let mut i = open + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'\'' => return i + 1,
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+fn skip_line_comment(bytes: &[u8], open: usize) -> usize {
+    match bytes[open..].iter().position(|&b| b == b'\n') {
+        Some(pos) => open + pos + 1,
+        None => bytes.len(),
+    }
+}
+
+// Block comments nest in Postgres
+fn skip_block_comment(bytes: &[u8], open: usize) -> usize {
+    let mut depth = 0;
+    let mut i = open;
+    while i + 1 < bytes.len() {
+        match (bytes[i], bytes[i + 1]) {
+            (b'/', b'*') => {
+                depth += 1;
+                i += 2;
+            }
+            (b'*', b'/') => {
+                depth -= 1;
+                i += 2;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+// Length of a `$tag$` opener at `open`, if there is one
+fn dollar_tag_len(bytes: &[u8], open: usize) -> Option<usize> {
+    // `$` inside an identifier (e.g. `foo$bar`) doesn't start a quote
+    if open > 0 && is_ident_byte(bytes[open - 1]) {
+        return None;
+    }
+
+    let mut i = open + 1;
+    while i < bytes.len() && is_ident_byte(bytes[i]) {
+        // Tags can't start with a digit, which keeps `$1` parameters out
+        if i == open + 1 && bytes[i].is_ascii_digit() {
+            return None;
+        }
+        i += 1;
+    }
+
+    (bytes.get(i) == Some(&b'$')).then_some(i + 1 - open)
+}
+
+fn skip_dollar_quoted(bytes: &[u8], open: usize, tag_len: usize) -> usize {
+    let tag = &bytes[open..open + tag_len];
+    let body = open + tag_len;
+    match bytes[body..].windows(tag_len).position(|w| w == tag) {
+        Some(pos) => body + pos + tag_len,
+        None => bytes.len(),
+    }
+}
+
+pub(super) fn is_ident_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b >= 0x80
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statements(sql: &str) -> Vec<&str> {
+        split_statements(sql).iter().map(|span| &sql[span.start..span.end]).collect()
+    }
+
+    #[test]
+    fn splits_on_top_level_semicolons() {
+        assert_eq!(statements("select 1; select 2;\n\nselect 3"), ["select 1", "select 2", "select 3"]);
+        assert_eq!(statements(" ;; \n"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn dollar_quoted_bodies_keep_their_semicolons() {
+        let sql = "create function f() returns int as $body$ select 1; $$ ; $body$ language sql; select $$a;b$$";
+        assert_eq!(
+            statements(sql),
+            ["create function f() returns int as $body$ select 1; $$ ; $body$ language sql", "select $$a;b$$"]
+        );
+        // Neither a parameter nor a `$` inside an identifier opens a body
+        assert_eq!(statements("select $1; select a$b; select 3"), ["select $1", "select a$b", "select 3"]);
+    }
+
+    #[test]
+    fn atomic_function_bodies_keep_their_semicolons() {
+        let body = "create function f(a int) returns int language sql\nbegin atomic\n  insert into t values (a);\n  select case when a > 0 then 1 else 0 end;\nend";
+        assert_eq!(statements(&format!("{};\nselect 2", body)), [body, "select 2"]);
+
+        let procedure = "create procedure p() begin atomic select 1; select case 'end' when 'end' then 2 end; end";
+        assert_eq!(statements(&format!("{}; select 3", procedure)), [procedure, "select 3"]);
+        // A transaction's BEGIN and END open and close nothing
+        assert_eq!(statements("begin; select 1; end; select 2"), ["begin", "select 1", "end", "select 2"]);
+        assert_eq!(statements("BEGIN ISOLATION LEVEL SERIALIZABLE; select 1"), ["BEGIN ISOLATION LEVEL SERIALIZABLE", "select 1"]);
+    }
+
+    #[test]
+    fn escape_strings_skip_backslashed_quotes() {
+        assert_eq!(statements(r"select E'\''; select 2"), [r"select E'\''", "select 2"]);
+        assert_eq!(statements(r"select e'it\'s; fine'; select 2"), [r"select e'it\'s; fine'", "select 2"]);
+        // Outside an E-string a backslash is literal, so the quote after it closes the string
+        assert_eq!(statements(r"select '\'; select 2"), [r"select '\'", "select 2"]);
+        assert_eq!(statements("select 'it''s; fine'; select 2"), ["select 'it''s; fine'", "select 2"]);
+    }
+
+    #[test]
+    fn block_comments_nest() {
+        assert_eq!(statements("select 1 /* /* ; */ ; */; select 2"), ["select 1", "select 2"]);
+        assert_eq!(statements("/* /* */ */ select 1 -- ; trailing\n; select 2"), ["select 1", "select 2"]);
+    }
+
+    #[test]
+    fn backslash_commands_run_to_the_end_of_their_line() {
+        assert_eq!(statements("select 1\n\\dt public.*  \nselect 2"), ["select 1", "\\dt public.*", "select 2"]);
+    }
+
+    #[test]
+    fn classifies_writable_ctes_by_what_they_do() {
+        assert_eq!(
+            classify_statement("WITH d AS (DELETE FROM t RETURNING *) INSERT INTO log SELECT * FROM d"),
+            ("INSERT".to_string(), StatementKind::Dml)
+        );
+        assert_eq!(
+            classify_statement("with moved as materialized (update t set x = 1 returning id) select count(*) from moved"),
+            ("SELECT".to_string(), StatementKind::Dml)
+        );
+        assert_eq!(
+            classify_statement("WITH a AS (WITH b AS (DELETE FROM t RETURNING *) SELECT * FROM b) SELECT * FROM a"),
+            ("SELECT".to_string(), StatementKind::Dml)
+        );
+        assert_eq!(
+            classify_statement("WITH r(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM r) SELECT * FROM r"),
+            ("SELECT".to_string(), StatementKind::Query)
+        );
+        // Row locks in a CTE don't make it a write
+        assert_eq!(
+            classify_statement("WITH l AS (SELECT * FROM t FOR UPDATE) SELECT * FROM l"),
+            ("SELECT".to_string(), StatementKind::Query)
+        );
+    }
+
+    #[test]
+    fn classifies_statement_kinds() {
+        assert_eq!(classify_statement("select 1").1, StatementKind::Query);
+        assert_eq!(classify_statement("/* c */ Update t set x = 1").0, "UPDATE");
+        assert_eq!(classify_statement("create table t (x int)").1, StatementKind::Ddl);
+        assert_eq!(classify_statement("begin isolation level serializable").1, StatementKind::Transaction);
+        assert_eq!(classify_statement("set search_path = app").1, StatementKind::Session);
+        assert_eq!(classify_statement("\\d+ t"), ("\\d+".to_string(), StatementKind::Meta));
+        assert_eq!(classify_statement("vacuum t"), ("VACUUM".to_string(), StatementKind::Other));
+    }
+
+    #[test]
+    fn tracks_the_transaction_status_statements_leave() {
+        use TransactionStatus::{Aborted, Active, Idle};
+        let after = |status, statement, succeeded| transaction_status_after(status, statement, succeeded);
+
+        assert_eq!(after(Idle, "begin isolation level serializable", true), Active);
+        assert_eq!(after(Idle, "START TRANSACTION READ ONLY", true), Active);
+        assert_eq!(after(Active, "insert into t values (1)", true), Active);
+        assert_eq!(after(Active, "/* done */ commit", true), Idle);
+        assert_eq!(after(Active, "end work", true), Idle);
+        assert_eq!(after(Active, "prepare transaction 'tx1'", true), Idle);
+        assert_eq!(after(Active, "commit and chain", true), Active);
+        assert_eq!(after(Active, "rollback work and no chain", true), Idle);
+        assert_eq!(after(Idle, "commit prepared 'tx1'", true), Idle);
+        // PREPARE of a statement isn't PREPARE TRANSACTION
+        assert_eq!(after(Active, "prepare q as select 1", true), Active);
+
+        // A failure aborts an open transaction and leaves an idle session idle
+        assert_eq!(after(Active, "select 1/0", false), Aborted);
+        assert_eq!(after(Idle, "select 1/0", false), Idle);
+        assert_eq!(after(Aborted, "select 1", false), Aborted);
+        // COMMIT in an aborted transaction rolls it back
+        assert_eq!(after(Aborted, "commit", true), Idle);
+        assert_eq!(after(Aborted, "abort", true), Idle);
+        assert_eq!(after(Aborted, "rollback to savepoint a", true), Active);
+        assert_eq!(after(Aborted, "rollback transaction to a", true), Active);
+        // A COMMIT that fails on a deferred constraint has ended the transaction
+        assert_eq!(after(Active, "commit", false), Idle);
+        assert_eq!(after(Active, "commit prepared 'tx1'", false), Aborted);
+    }
+
+    #[test]
+    fn cursor_offsets_at_statement_boundaries() {
+        let sql = "  select 1;\nselect 2 ;  select 3";
+        let spans = split_statements(sql);
+        let first_end = sql.find(';').unwrap();
+        let second_start = sql.find("select 2").unwrap();
+        let third_start = sql.find("select 3").unwrap();
+
+        // Before the first statement
+        assert_eq!(statement_at(&spans, 0), Some(0));
+        // On the end of a statement, its semicolon, and the whitespace after it
+        assert_eq!(statement_at(&spans, first_end), Some(0));
+        assert_eq!(statement_at(&spans, first_end + 1), Some(0));
+        assert_eq!(statement_at(&spans, second_start - 1), Some(0));
+        // On the first byte of the next one
+        assert_eq!(statement_at(&spans, second_start), Some(1));
+        assert_eq!(statement_at(&spans, third_start - 1), Some(1));
+        assert_eq!(statement_at(&spans, third_start), Some(2));
+        // Past the end of the script
+        assert_eq!(statement_at(&spans, sql.len()), Some(2));
+        assert_eq!(statement_at(&[], 0), None);
+    }
+}
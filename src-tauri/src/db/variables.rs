@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::db::splitter::{is_ident_byte, skip_comment, skip_literal};
+use crate::error::AppError;
+use crate::models::StatementSpan;
+
+pub struct Substituted {
+    pub sql: String,
+    // `\set` / `\unset` lines in the order they ran; None means unset
+    pub changes: Vec<(String, Option<String>)>,
+    // Each replaced reference or removed line, in order, so offsets into `sql` can be
+    // traced back to the text the user wrote
+    edits: Vec<Edit>,
+}
+
+struct Edit {
+    output: Range<usize>,
+    original: Range<usize>,
+}
+
+impl Substituted {
+    // The original byte offset for one into `sql`. An offset inside a replaced value maps to
+    // the start of its reference, or to the end when `round_up` is set.
+    pub fn original_offset(&self, offset: usize, round_up: bool) -> usize {
+        let mut end = (0, 0);
+        for edit in &self.edits {
+            if offset <= edit.output.start {
+                break;
+            }
+            if offset < edit.output.end {
+                return if round_up { edit.original.end } else { edit.original.start };
+            }
+            end = (edit.output.end, edit.original.end);
+        }
+        offset - end.0 + end.1
+    }
+
+    pub fn original_span(&self, span: &StatementSpan) -> StatementSpan {
+        StatementSpan {
+            start: self.original_offset(span.start, false),
+            end: self.original_offset(span.end, true),
+        }
+    }
+
+    // Server error positions count characters from 1
+    pub fn original_position(&self, original: &str, position: usize) -> usize {
+        let Some(index) = position.checked_sub(1) else {
+            return position;
+        };
+        let offset = self.sql.char_indices().nth(index).map_or(self.sql.len(), |(i, _)| i);
+        let offset = self.original_offset(offset, false).min(original.len());
+        original[..offset].chars().count() + 1
+    }
+}
+
+// Expands psql-style `:name`, `:'name'` and `:"name"` references outside quoted text and
+// comments, including in the arguments of backslash commands, and runs `\set` / `\unset`
+// lines, which are removed from the script. As in psql, a reference to a variable that isn't
+// set is left as written.
+pub fn substitute_variables(sql: &str, variables: &HashMap<String, String>) -> Result<Substituted, AppError> {
+    let mut vars = variables.clone();
+    let mut changes = Vec::new();
+    let mut edits = Vec::new();
+
+    let bytes = sql.as_bytes();
+    let mut out = String::with_capacity(sql.len());
+    // Everything before `copied` is already in `out`
+    let mut copied = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if let Some(end) = skip_comment(bytes, i).or_else(|| skip_literal(bytes, i)) {
+            i = end;
+            continue;
+        }
+
+        match bytes[i] {
+            // `::` is a cast, not a reference
+            b':' if bytes.get(i + 1) == Some(&b':') => i += 2,
+            b':' => match variable_reference(sql, i, &vars) {
+                Some((end, text)) => {
+                    out.push_str(&sql[copied..i]);
+                    edits.push(Edit { output: out.len()..out.len() + text.len(), original: i..end });
+                    out.push_str(&text);
+                    i = end;
+                    copied = end;
+                }
+                None => i += 1,
+            },
+            // A backslash command runs to the end of the line
+            b'\\' => {
+                let end = bytes[i..].iter().position(|&b| b == b'\n').map_or(bytes.len(), |pos| i + pos);
+                if let Some((name, value)) = parse_set_command(&sql[i + 1..end], &vars)? {
+                    out.push_str(&sql[copied..i]);
+                    edits.push(Edit { output: out.len()..out.len(), original: i..end });
+                    match &value {
+                        Some(value) => vars.insert(name.clone(), value.clone()),
+                        None => vars.remove(&name),
+                    };
+                    changes.push((name, value));
+                    copied = end;
+                    i = end;
+                    continue;
+                }
+
+                // Other commands have references in their arguments expanded, outside
+                // single-quoted ones, as psql does
+                let mut j = i + 1;
+                while j < end {
+                    match bytes[j] {
+                        b'\'' => {
+                            j = bytes[j + 1..end].iter().position(|&b| b == b'\'').map_or(end, |pos| j + pos + 2);
+                        }
+                        b':' => match variable_reference(sql, j, &vars) {
+                            Some((reference_end, text)) => {
+                                out.push_str(&sql[copied..j]);
+                                edits.push(Edit { output: out.len()..out.len() + text.len(), original: j..reference_end });
+                                out.push_str(&text);
+                                j = reference_end;
+                                copied = reference_end;
+                            }
+                            None => j += 1,
+                        },
+                        _ => j += 1,
+                    }
+                }
+                i = end;
+            }
+            _ => i += 1,
+        }
+    }
+
+    out.push_str(&sql[copied..]);
+    Ok(Substituted { sql: out, changes, edits })
+}
+
+// Replacement text and end offset for a reference starting at the `:` at `colon`
+fn variable_reference(sql: &str, colon: usize, vars: &HashMap<String, String>) -> Option<(usize, String)> {
+    let bytes = sql.as_bytes();
+    let quote = bytes.get(colon + 1).copied().filter(|&b| b == b'\'' || b == b'"');
+
+    let name_start = colon + 1 + quote.is_some() as usize;
+    let name_end = ident_end(bytes, name_start);
+    if name_end == name_start {
+        return None;
+    }
+    let value = vars.get(&sql[name_start..name_end])?;
+
+    match quote {
+        Some(q) if bytes.get(name_end) == Some(&q) => {
+            let text = if q == b'\'' { quote_literal(value) } else { quote_identifier(value) };
+            Some((name_end + 1, text))
+        }
+        Some(_) => None,
+        None => Some((name_end, value.clone())),
+    }
+}
+
+fn ident_end(bytes: &[u8], start: usize) -> usize {
+    bytes[start..]
+        .iter()
+        .position(|&b| !is_ident_byte(b))
+        .map_or(bytes.len(), |pos| start + pos)
+}
+
+// Backslashes are doubled inside an E'' literal so the value reads the same whatever
+// standard_conforming_strings is set to
+pub fn quote_literal(value: &str) -> String {
+    let escaped = value.replace('\'', "''");
+    if value.contains('\\') {
+        format!("E'{}'", escaped.replace('\\', "\\\\"))
+    } else {
+        format!("'{}'", escaped)
+    }
+}
+
+pub fn quote_identifier(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+// `\set name [value ...]` concatenates its values like psql; `\unset name` clears the variable.
+// Other backslash commands aren't handled here and come back as None.
+fn parse_set_command(line: &str, vars: &HashMap<String, String>) -> Result<Option<(String, Option<String>)>, AppError> {
+    let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let unset = match command {
+        "set" => false,
+        "unset" => true,
+        _ => return Ok(None),
+    };
+    let mut args = meta_args(rest, vars)?.into_iter();
+
+    // A bare `\set` lists variables in psql, which has no meaning inside a script
+    let Some(name) = args.next() else {
+        return Ok(None);
+    };
+    if name.is_empty() || !name.bytes().all(is_ident_byte) {
+        return Err(AppError::other(format!("Invalid variable name: {}", name)));
+    }
+
+    let value = (!unset).then(|| args.collect::<String>());
+    Ok(Some((name, value)))
+}
+
+// Splits meta-command arguments on whitespace, unquoting '...' and expanding `:name` references
+fn meta_args(text: &str, vars: &HashMap<String, String>) -> Result<Vec<String>, AppError> {
+    let mut args = Vec::new();
+    let mut chars = text.trim().chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let mut arg = String::new();
+        if c == '\'' {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('\'') if chars.peek() == Some(&'\'') => {
+                        chars.next();
+                        arg.push('\'');
+                    }
+                    Some('\'') => break,
+                    Some(c) => arg.push(c),
+                    None => return Err(AppError::other("Unterminated quoted string in \\set")),
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                arg.push(c);
+                chars.next();
+            }
+            if let Some(value) = arg.strip_prefix(':').and_then(|name| vars.get(name)) {
+                arg = value.clone();
+            }
+        }
+        args.push(arg);
+    }
+
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    fn substitute(sql: &str, pairs: &[(&str, &str)]) -> String {
+        substitute_variables(sql, &vars(pairs)).unwrap().sql
+    }
+
+    #[test]
+    fn expands_bare_references() {
+        assert_eq!(substitute("select * from :tbl where id = :id", &[("tbl", "users"), ("id", "42")]), "select * from users where id = 42");
+        // Unset variables are left as written
+        assert_eq!(substitute("select :missing, :'missing', :\"missing\"", &[]), "select :missing, :'missing', :\"missing\"");
+    }
+
+    #[test]
+    fn quotes_literal_and_identifier_references() {
+        let pairs = [("name", "O'Brien"), ("path", r"C:\temp"), ("both", r"it's \n"), ("col", "Mixed \"Case\"")];
+        assert_eq!(substitute("select :'name'", &pairs), "select 'O''Brien'");
+        assert_eq!(substitute("select :'path'", &pairs), r"select E'C:\\temp'");
+        assert_eq!(substitute("select :'both'", &pairs), r"select E'it''s \\n'");
+        assert_eq!(substitute("select :\"col\" from t", &pairs), "select \"Mixed \"\"Case\"\"\" from t");
+        // A quote that isn't closed right after the name isn't a reference
+        assert_eq!(substitute("select :'name", &pairs), "select :'name");
+        assert_eq!(substitute("select :'name\"", &pairs), "select :'name\"");
+    }
+
+    #[test]
+    fn leaves_casts_alone() {
+        let pairs = [("text", "oops"), ("id", "7")];
+        assert_eq!(substitute("select '1'::text, :id::int, x::text", &pairs), "select '1'::text, 7::int, x::text");
+        assert_eq!(substitute("select :'id'::int", &pairs), "select '7'::int");
+    }
+
+    #[test]
+    fn skips_literals_comments_and_dollar_quoted_bodies() {
+        let pairs = [("x", "1")];
+        for sql in [
+            "select ':x'",
+            "select E'\\' :x'",
+            "select \":x\"",
+            "select 1 -- :x",
+            "select 1 /* :x /* :x */ :x */",
+            "select $$ :x $$",
+            "select $fn$ :x $fn$",
+        ] {
+            assert_eq!(substitute(sql, &pairs), sql);
+        }
+        assert_eq!(substitute("select $$:x$$, :x", &pairs), "select $$:x$$, 1");
+    }
+
+    #[test]
+    fn runs_set_and_unset_lines() {
+        let result = substitute_variables(
+            "\\set tbl users\nselect * from :tbl;\n\\set greeting 'hello ' :tbl\nselect :'greeting';\n\\unset tbl\nselect :tbl",
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(result.sql, "\nselect * from users;\n\nselect 'hello users';\n\nselect :tbl");
+        assert_eq!(
+            result.changes,
+            [
+                ("tbl".to_string(), Some("users".to_string())),
+                ("greeting".to_string(), Some("hello users".to_string())),
+                ("tbl".to_string(), None),
+            ]
+        );
+
+        // Other backslash commands have their arguments expanded, except inside quotes
+        assert_eq!(substitute("\\dt :x", &[("x", "orders")]), "\\dt orders");
+        assert_eq!(substitute("\\d :\"x\" ':x'", &[("x", "Orders")]), "\\d \"Orders\" ':x'");
+        assert_eq!(substitute("\\set s app\n\\dt :s.*", &[]), "\n\\dt app.*");
+        assert!(substitute_variables("\\set 'bad name' 1", &HashMap::new()).is_err());
+        assert!(substitute_variables("\\set x 'open", &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn maps_offsets_back_to_the_original() {
+        let sql = "\\set t orders\nselect :id from :t;\nselect bad syntax";
+        let result = substitute_variables(sql, &vars(&[("id", "1234567890")])).unwrap();
+        assert_eq!(result.sql, "\nselect 1234567890 from orders;\nselect bad syntax");
+
+        // The value is longer than its reference, and the `\set` line is gone altogether
+        let second = result.sql.find("select bad").unwrap();
+        assert_eq!(result.original_offset(second, false), sql.find("select bad").unwrap());
+        let first = StatementSpan { start: 1, end: result.sql.find(';').unwrap() + 1 };
+        let span = result.original_span(&first);
+        assert_eq!(&sql[span.start..span.end], "select :id from :t;");
+
+        // Inside a value maps to its reference
+        let inside = result.sql.find("4567").unwrap();
+        assert_eq!(result.original_offset(inside, false), sql.find(":id").unwrap());
+        assert_eq!(result.original_offset(inside, true), sql.find(" from").unwrap());
+
+        // Error positions count characters from 1
+        let bad = result.sql[..result.sql.find("bad").unwrap()].chars().count() + 1;
+        assert_eq!(result.original_position(sql, bad), sql.find("bad").unwrap() + 1);
+        let multibyte = "select 'é', :x, oops";
+        let result = substitute_variables(multibyte, &vars(&[("x", "'a much longer value'")])).unwrap();
+        let oops = result.sql[..result.sql.find("oops").unwrap()].chars().count() + 1;
+        assert_eq!(result.original_position(multibyte, oops), multibyte[..multibyte.find("oops").unwrap()].chars().count() + 1);
+    }
+}
@@ -0,0 +1,182 @@
+use serde::Serialize;
+use sqlx::postgres::{PgDatabaseError, PgErrorPosition, PgSeverity};
+use std::fmt;
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AppError {
+    // Error reported by the server while running a statement
+    Database(Box<DatabaseError>),
+    // Network, TLS, authentication or pool failures
+    Connection { message: String },
+    NotFound { message: String },
+    // Reading or writing files in the app data dir
+    Storage { message: String },
+    // The OS keyring or the encrypted credential file failed
+    Credentials { message: String },
+    // A secret is needed from the encrypted credential file, which has to be unlocked first
+    CredentialsLocked { message: String },
+    Other { message: String },
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DatabaseError {
+    pub code: String,
+    pub severity: String,
+    pub message: String,
+    pub detail: Option<String>,
+    pub hint: Option<String>,
+    // 1-based character offset into the submitted query text
+    pub position: Option<usize>,
+    // Set when the error comes from a query generated internally (e.g. inside a function)
+    pub internal_position: Option<usize>,
+    pub internal_query: Option<String>,
+    pub context: Option<String>,
+    pub schema: Option<String>,
+    pub table: Option<String>,
+    pub column: Option<String>,
+    pub data_type: Option<String>,
+    pub constraint: Option<String>,
+}
+
+impl AppError {
+    pub fn connection(message: impl Into<String>) -> Self {
+        AppError::Connection { message: message.into() }
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        AppError::NotFound { message: message.into() }
+    }
+
+    pub fn storage(message: impl Into<String>) -> Self {
+        AppError::Storage { message: message.into() }
+    }
+
+    pub fn credentials(message: impl Into<String>) -> Self {
+        AppError::Credentials { message: message.into() }
+    }
+
+    pub fn credentials_locked(message: impl Into<String>) -> Self {
+        AppError::CredentialsLocked { message: message.into() }
+    }
+
+    pub fn other(message: impl Into<String>) -> Self {
+        AppError::Other { message: message.into() }
+    }
+
+    // Moves a server error position, e.g. when the text that ran differs from the text shown
+    pub fn map_position(mut self, f: impl FnOnce(usize) -> usize) -> Self {
+        if let AppError::Database(e) = &mut self {
+            e.position = e.position.map(f);
+        }
+        self
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::Database(e) => &e.message,
+            AppError::Connection { message }
+            | AppError::NotFound { message }
+            | AppError::Storage { message }
+            | AppError::Credentials { message }
+            | AppError::CredentialsLocked { message }
+            | AppError::Other { message } => message,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Database(e) => write!(f, "{}: {}", e.severity, e.message),
+            _ => f.write_str(self.message()),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+pub fn severity_name(severity: PgSeverity) -> &'static str {
+    match severity {
+        PgSeverity::Panic => "PANIC",
+        PgSeverity::Fatal => "FATAL",
+        PgSeverity::Error => "ERROR",
+        PgSeverity::Warning => "WARNING",
+        PgSeverity::Notice => "NOTICE",
+        PgSeverity::Debug => "DEBUG",
+        PgSeverity::Info => "INFO",
+        PgSeverity::Log => "LOG",
+    }
+}
+
+impl From<&PgDatabaseError> for DatabaseError {
+    fn from(e: &PgDatabaseError) -> Self {
+        let (position, internal_position, internal_query) = match e.position() {
+            Some(PgErrorPosition::Original(position)) => (Some(position), None, None),
+            Some(PgErrorPosition::Internal { position, query }) => {
+                (None, Some(position), Some(query.to_string()))
+            }
+            None => (None, None, None),
+        };
+
+        DatabaseError {
+            code: e.code().to_string(),
+            severity: severity_name(e.severity()).to_string(),
+            message: e.message().to_string(),
+            detail: e.detail().map(str::to_string),
+            hint: e.hint().map(str::to_string),
+            position,
+            internal_position,
+            internal_query,
+            context: e.r#where().map(str::to_string),
+            schema: e.schema().map(str::to_string),
+            table: e.table().map(str::to_string),
+            column: e.column().map(str::to_string),
+            data_type: e.data_type().map(str::to_string),
+            constraint: e.constraint().map(str::to_string),
+        }
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(err: sqlx::Error) -> Self {
+        match &err {
+            sqlx::Error::Database(db) => match db.try_downcast_ref::<PgDatabaseError>() {
+                Some(pg) => AppError::Database(Box::new(pg.into())),
+                None => AppError::other(db.message()),
+            },
+            sqlx::Error::Configuration(_)
+            | sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::Protocol(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::WorkerCrashed => AppError::connection(err.to_string()),
+            _ => AppError::other(err.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(err: std::io::Error) -> Self {
+        AppError::storage(err.to_string())
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(err: serde_json::Error) -> Self {
+        AppError::storage(err.to_string())
+    }
+}
+
+impl From<tauri::Error> for AppError {
+    fn from(err: tauri::Error) -> Self {
+        AppError::storage(err.to_string())
+    }
+}
+
+impl<T> From<std::sync::PoisonError<T>> for AppError {
+    fn from(err: std::sync::PoisonError<T>) -> Self {
+        AppError::other(err.to_string())
+    }
+}
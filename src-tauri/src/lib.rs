@@ -1,28 +1,90 @@
 mod db;
+mod error;
 mod models;
 mod state;
 mod commands;
+mod credentials;
 mod storage;
+mod tunnel;
 
 use state::AppState;
+use tauri::Manager;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    // Routes server notices reported by sqlx into the query that raised them
+    tracing_subscriber::registry()
+        .with(db::notices::NoticeLayer)
+        .init();
+
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .manage(AppState::new())
+        .setup(|app| {
+            let handle = app.handle().clone();
+            // Defaults to the keyring when the settings can't be read; a corrupted file is
+            // reported through take_config_problems
+            if let Ok(settings) = storage::load_settings(&handle) {
+                let _ = handle.state::<AppState>().credentials.set_preferred(settings.credential_backend);
+            }
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(commands::CURSOR_IDLE_TIMEOUT / 5);
+                loop {
+                    interval.tick().await;
+                    commands::expire_idle_cursors(&handle.state::<AppState>()).await;
+                }
+            });
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             commands::connect_db,
             commands::disconnect_db,
             commands::run_query,
+            commands::run_query_stream,
+            commands::describe_query,
+            commands::explain_query,
+            commands::run_script,
+            commands::split_script,
+            commands::run_statement_at,
+            commands::cancel_query,
+            commands::fetch_next_page,
+            commands::close_cursor,
+            commands::close_tab,
+            commands::begin_transaction,
+            commands::commit,
+            commands::rollback,
+            commands::transaction_status,
+            commands::pool_status,
+            commands::pin_session,
+            commands::reset_session,
+            commands::release_session,
+            commands::get_variables,
+            commands::set_variables,
+            commands::search_history,
+            commands::delete_history_entry,
+            commands::clear_history,
+            commands::reopen_history_entry,
             commands::get_schemas,
             commands::get_tables,
             commands::save_connection,
             commands::load_connections,
             commands::delete_connection,
+            commands::import_connection_string,
+            commands::export_connection_string,
+            commands::credential_status,
+            commands::unlock_credentials,
+            commands::lock_credentials,
+            commands::set_credential_backend,
+            commands::save_snippet,
+            commands::load_snippets,
+            commands::delete_snippet,
+            commands::search_snippets,
             commands::save_session,
             commands::save_session,
             commands::load_session,
+            commands::take_config_problems,
             commands::update_cell,
             commands::get_columns,
             commands::update_connections_list
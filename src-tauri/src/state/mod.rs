@@ -1,27 +1,214 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
-use crate::db::DatabaseDriver;
+use std::time::Instant;
+use crate::credentials::CredentialManager;
+use crate::db::{DatabaseDriver, QueryCursor};
+use crate::error::AppError;
+use crate::models::{ConfigProblem, VariableScope};
+use crate::tunnel::SshTunnel;
 
 pub struct ConnectionRegistry {
     pub connections: Mutex<HashMap<String, Arc<dyn DatabaseDriver>>>,
+    pub details: Mutex<HashMap<String, ConnectionDetails>>,
+    // SSH tunnels of connections opened through a jump host, closed on disconnect
+    pub tunnels: Mutex<HashMap<String, SshTunnel>>,
 }
 
 impl ConnectionRegistry {
     pub fn new() -> Self {
         Self {
             connections: Mutex::new(HashMap::new()),
+            details: Mutex::new(HashMap::new()),
+            tunnels: Mutex::new(HashMap::new()),
         }
     }
 }
 
+// What a live connection was opened from, for labelling history entries
+#[derive(Clone)]
+pub struct ConnectionDetails {
+    pub saved_connection_id: Option<String>,
+    pub name: Option<String>,
+    pub database: String,
+}
+
+#[derive(Clone)]
+pub struct OpenCursor {
+    pub connection_id: String,
+    // The tab the result set is shown in
+    pub session_id: Option<String>,
+    pub cursor: Arc<tokio::sync::Mutex<Box<dyn QueryCursor>>>,
+    pub last_used: Instant,
+}
+
+// Each open cursor holds a pooled connection in an open transaction, so they're capped per
+// connection and closed once idle, on tab close and on disconnect
+pub struct CursorRegistry {
+    pub cursors: Mutex<HashMap<String, OpenCursor>>,
+}
+
+impl CursorRegistry {
+    pub fn new() -> Self {
+        Self {
+            cursors: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Removes the matching cursors, leaving the caller to close them
+    pub fn take_where(&self, matches: impl Fn(&OpenCursor) -> bool) -> Result<Vec<OpenCursor>, AppError> {
+        let mut cursors = self.cursors.lock()?;
+        let ids: Vec<String> = cursors
+            .iter()
+            .filter(|(_, open)| matches(open))
+            .map(|(id, _)| id.clone())
+            .collect();
+        Ok(ids.iter().filter_map(|id| cursors.remove(id)).collect())
+    }
+
+    // Removes the connection's least recently used cursors until fewer than `limit` are left
+    pub fn take_over_limit(&self, connection_id: &str, limit: usize) -> Result<Vec<OpenCursor>, AppError> {
+        let mut cursors = self.cursors.lock()?;
+        let mut ids: Vec<(Instant, String)> = cursors
+            .iter()
+            .filter(|(_, open)| open.connection_id == connection_id)
+            .map(|(id, open)| (open.last_used, id.clone()))
+            .collect();
+        ids.sort();
+
+        let excess = (ids.len() + 1).saturating_sub(limit);
+        Ok(ids
+            .iter()
+            .take(excess)
+            .filter_map(|(_, id)| cursors.remove(id))
+            .collect())
+    }
+
+    pub fn touch(&self, result_id: &str) -> Result<(), AppError> {
+        if let Some(open) = self.cursors.lock()?.get_mut(result_id) {
+            open.last_used = Instant::now();
+        }
+        Ok(())
+    }
+}
+
+type Variables = HashMap<String, String>;
+
+// Script variables keyed by live connection id and by tab id
+pub struct VariableStore {
+    pub connections: Mutex<HashMap<String, Variables>>,
+    pub tabs: Mutex<HashMap<String, Variables>>,
+}
+
+impl VariableStore {
+    pub fn new() -> Self {
+        Self {
+            connections: Mutex::new(HashMap::new()),
+            tabs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn scope(&self, scope: VariableScope) -> &Mutex<HashMap<String, Variables>> {
+        match scope {
+            VariableScope::Connection => &self.connections,
+            VariableScope::Tab => &self.tabs,
+        }
+    }
+
+    pub fn get(&self, scope: VariableScope, scope_id: &str) -> Result<Variables, AppError> {
+        let values = self.scope(scope).lock()?;
+        Ok(values.get(scope_id).cloned().unwrap_or_default())
+    }
+
+    pub fn set(&self, scope: VariableScope, scope_id: &str, variables: Variables) -> Result<(), AppError> {
+        let mut values = self.scope(scope).lock()?;
+        values.insert(scope_id.to_string(), variables);
+        Ok(())
+    }
+
+    pub fn remove(&self, scope: VariableScope, scope_id: &str) -> Result<(), AppError> {
+        let mut values = self.scope(scope).lock()?;
+        values.remove(scope_id);
+        Ok(())
+    }
+
+    // Connection values overlaid with the tab's own
+    pub fn resolve(&self, connection_id: &str, tab_id: Option<&str>) -> Result<Variables, AppError> {
+        let mut resolved = self.get(VariableScope::Connection, connection_id)?;
+        if let Some(tab_id) = tab_id {
+            resolved.extend(self.get(VariableScope::Tab, tab_id)?);
+        }
+        Ok(resolved)
+    }
+
+    // `\set` in a script updates the tab it ran in
+    pub fn apply(&self, tab_id: &str, changes: &[(String, Option<String>)]) -> Result<(), AppError> {
+        let mut tabs = self.tabs.lock()?;
+        let values = tabs.entry(tab_id.to_string()).or_default();
+        for (name, value) in changes {
+            match value {
+                Some(value) => values.insert(name.clone(), value.clone()),
+                None => values.remove(name),
+            };
+        }
+        Ok(())
+    }
+}
+
+// Result display settings that scripts change per tab, e.g. psql's \x
+pub struct DisplaySettings {
+    pub expanded_tabs: Mutex<HashSet<String>>,
+}
+
+impl DisplaySettings {
+    pub fn new() -> Self {
+        Self {
+            expanded_tabs: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn is_expanded(&self, tab_id: &str) -> bool {
+        self.expanded_tabs
+            .lock()
+            .map(|tabs| tabs.contains(tab_id))
+            .unwrap_or(false)
+    }
+
+    // None toggles the current setting; returns the new one
+    pub fn set_expanded(&self, tab_id: &str, expanded: Option<bool>) -> Result<bool, AppError> {
+        let mut tabs = self.expanded_tabs.lock()?;
+        let expanded = expanded.unwrap_or(!tabs.contains(tab_id));
+        if expanded {
+            tabs.insert(tab_id.to_string());
+        } else {
+            tabs.remove(tab_id);
+        }
+        Ok(expanded)
+    }
+}
+
 pub struct AppState {
     pub registry: ConnectionRegistry,
+    pub cursors: CursorRegistry,
+    pub variables: VariableStore,
+    pub display: DisplaySettings,
+    pub credentials: CredentialManager,
+    // Config files found corrupted since the frontend last asked
+    pub config_problems: Mutex<Vec<ConfigProblem>>,
+    // Held while the history files are appended to, rotated or rewritten, so a rotation
+    // never moves a file another query is writing to
+    pub history_lock: Mutex<()>,
 }
 
 impl AppState {
     pub fn new() -> Self {
         Self {
             registry: ConnectionRegistry::new(),
+            cursors: CursorRegistry::new(),
+            variables: VariableStore::new(),
+            display: DisplaySettings::new(),
+            credentials: CredentialManager::new(),
+            config_problems: Mutex::new(Vec::new()),
+            history_lock: Mutex::new(()),
         }
     }
 }
@@ -1,53 +1,353 @@
+use std::collections::{HashMap, VecDeque};
 use std::fs;
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, FixedOffset, Utc};
 use tauri::{AppHandle, Manager};
-use crate::models::SavedConnection;
+use crate::credentials::CredentialManager;
+use crate::error::AppError;
+use crate::models::{AppSettings, ConfigProblem, HistoryEntry, HistoryFilter, SavedConnection, SavedQuery};
+use crate::state::AppState;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+// Rolling copies kept of each config file, `<name>.bak.1` being the newest
+const BACKUP_COUNT: usize = 3;
+
+// Upgrades a config file's JSON by one version. A file's current version is the number of
+// migrations it has; a file without a `version` field is version 0.
+type Migration = fn(Value) -> Result<Value, AppError>;
+
+// Writes a config file atomically, keeping the old contents as the newest backup first
+pub fn write_config(path: &Path, content: &str) -> Result<(), AppError> {
+    write_file(path, content, true)
+}
+
+// Writes to a temporary file and renames it over the target, so a crash leaves either the old
+// or the new contents. No backups are kept, for files holding secrets or too large to copy.
+pub fn write_atomic(path: &Path, content: &str) -> Result<(), AppError> {
+    write_file(path, content, false)
+}
+
+fn write_file(path: &Path, content: &str, keep_backup: bool) -> Result<(), AppError> {
+    let tmp = sibling_path(path, &format!("{}.tmp", uuid::Uuid::new_v4().simple()));
+    let written = fs::File::create(&tmp).and_then(|mut file| {
+        file.write_all(content.as_bytes())?;
+        file.sync_all()
+    });
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+
+    if keep_backup && path.exists() {
+        rotate_backups(path)?;
+    }
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+// Deletes backups written by versions that kept them for every file
+pub fn remove_backups(path: &Path) -> Result<(), AppError> {
+    for backup in (1..=BACKUP_COUNT).map(|n| backup_path(path, n)).filter(|b| b.exists()) {
+        fs::remove_file(backup)?;
+    }
+    Ok(())
+}
+
+fn rotate_backups(path: &Path) -> Result<(), AppError> {
+    for n in (1..BACKUP_COUNT).rev() {
+        let older = backup_path(path, n);
+        if older.exists() {
+            fs::rename(&older, backup_path(path, n + 1))?;
+        }
+    }
+    fs::copy(path, backup_path(path, 1))?;
+    Ok(())
+}
+
+fn backup_path(path: &Path, n: usize) -> PathBuf {
+    sibling_path(path, &format!("bak.{}", n))
+}
+
+// `<file name>.<suffix>` next to `path`
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    path.with_file_name(format!("{}.{}", name, suffix))
+}
+
+enum ConfigError {
+    // Written by a newer version of the app; left alone rather than treated as corrupt
+    Newer(u64),
+    Invalid(String),
+}
+
+// Reads a versioned config file, running any migrations it needs and saving the upgraded file.
+// A file that can't be read is kept aside as `<name>.corrupt-<time>` and the newest readable
+// backup takes its place; either way the problem is returned so it can be shown to the user.
+pub fn read_config<T: Serialize + DeserializeOwned>(
+    path: &Path,
+    migrations: &[Migration],
+) -> Result<(Option<T>, Option<ConfigProblem>), AppError> {
+    if !path.exists() {
+        return Ok((None, None));
+    }
+
+    let error = match parse_config::<T>(path, migrations) {
+        Ok((config, migrated)) => {
+            if migrated {
+                write_config(path, &serde_json::to_string_pretty(&config)?)?;
+            }
+            return Ok((Some(config), None));
+        }
+        Err(ConfigError::Newer(version)) => {
+            return Err(AppError::storage(format!(
+                "{} is version {}, written by a newer version of the app",
+                path.display(),
+                version
+            )));
+        }
+        Err(ConfigError::Invalid(error)) => error,
+    };
+
+    let preserved = sibling_path(path, &format!("corrupt-{}", Utc::now().format("%Y%m%dT%H%M%S")));
+    fs::rename(path, &preserved)?;
+
+    let mut restored = None;
+    for backup in (1..=BACKUP_COUNT).map(|n| backup_path(path, n)).filter(|b| b.exists()) {
+        if let Ok((config, _)) = parse_config::<T>(&backup, migrations) {
+            fs::copy(&backup, path)?;
+            restored = Some((backup, config));
+            break;
+        }
+    }
+
+    let problem = ConfigProblem {
+        file: path.display().to_string(),
+        error,
+        preserved_as: preserved.display().to_string(),
+        restored_from: restored.as_ref().map(|(backup, _)| backup.display().to_string()),
+    };
+    Ok((restored.map(|(_, config)| config), Some(problem)))
+}
+
+// The parsed file and whether it had to be migrated
+fn parse_config<T: DeserializeOwned>(path: &Path, migrations: &[Migration]) -> Result<(T, bool), ConfigError> {
+    let invalid = |e: &dyn std::fmt::Display| ConfigError::Invalid(e.to_string());
+
+    let content = fs::read_to_string(path).map_err(|e| invalid(&e))?;
+    let mut value: Value = serde_json::from_str(&content).map_err(|e| invalid(&e))?;
+
+    let version = value.get("version").and_then(Value::as_u64).unwrap_or(0);
+    if version > migrations.len() as u64 {
+        return Err(ConfigError::Newer(version));
+    }
+    for migrate in &migrations[version as usize..] {
+        value = migrate(value).map_err(|e| invalid(&e))?;
+    }
+
+    let config = serde_json::from_value(value).map_err(|e| invalid(&e))?;
+    Ok((config, version < migrations.len() as u64))
+}
+
+// Problems are kept until the frontend collects them with take_config_problems
+fn report_problem(app: &AppHandle, problem: Option<ConfigProblem>) {
+    if let Some(problem) = problem {
+        if let Ok(mut problems) = app.state::<AppState>().config_problems.lock() {
+            problems.push(problem);
+        }
+    }
+}
 
 const FILE_NAME: &str = "connections.json";
 
-fn get_connections_path(app: &AppHandle) -> Result<PathBuf, String> {
-    let mut path = app.path().app_data_dir().map_err(|e| e.to_string())?;
-    fs::create_dir_all(&path).map_err(|e| e.to_string())?;
+#[derive(Serialize, Deserialize)]
+struct ConnectionsFile {
+    version: u32,
+    connections: Vec<SavedConnection>,
+}
+
+const CONNECTIONS_MIGRATIONS: &[Migration] = &[connections_v0_to_v1];
+
+// v0 was a bare array of connections
+fn connections_v0_to_v1(value: Value) -> Result<Value, AppError> {
+    match value {
+        Value::Array(connections) => Ok(json!({ "version": 1, "connections": connections })),
+        _ => Err(AppError::storage("Expected a list of connections")),
+    }
+}
+
+fn get_connections_path(app: &AppHandle) -> Result<PathBuf, AppError> {
+    let mut path = app.path().app_data_dir()?;
+    fs::create_dir_all(&path)?;
     path.push(FILE_NAME);
     Ok(path)
 }
 
-pub fn load_connections(app: &AppHandle) -> Result<Vec<SavedConnection>, String> {
-    let path = get_connections_path(app)?;
-    
-    if !path.exists() {
-        return Ok(Vec::new());
+// Plaintext passwords left by older versions are moved into the credential store as they
+// load. If the store can't take them yet (e.g. the credential file is locked) they stay
+// until a later load succeeds.
+pub fn load_connections(app: &AppHandle, credentials: &CredentialManager) -> Result<Vec<SavedConnection>, AppError> {
+    let mut connections = read_connections(app)?;
+
+    let mut migrated = false;
+    for connection in connections.iter_mut().filter(|c| has_plaintext_secret(c)) {
+        match secure_secrets(credentials, connection) {
+            Ok(()) => migrated = true,
+            Err(_) => break,
+        }
+    }
+    if migrated {
+        write_connections(app, &connections)?;
     }
 
-    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
-    let connections: Vec<SavedConnection> = serde_json::from_str(&content).map_err(|e| e.to_string())?;
     Ok(connections)
 }
 
-pub fn save_connections(app: &AppHandle, connections: &[SavedConnection]) -> Result<(), String> {
-    let path = get_connections_path(app)?;
-    let content = serde_json::to_string_pretty(connections).map_err(|e| e.to_string())?;
-    fs::write(path, content).map_err(|e| e.to_string())?;
-    Ok(())
+// Passwords go to the credential store and only their references are written. Secrets of
+// connections no longer in the list are removed where the store allows it.
+pub fn save_connections(app: &AppHandle, credentials: &CredentialManager, connections: &[SavedConnection]) -> Result<(), AppError> {
+    let mut connections = connections.to_vec();
+    for connection in &mut connections {
+        secure_secrets(credentials, connection)?;
+    }
+
+    let kept: Vec<&str> = connections.iter().flat_map(secret_refs).collect();
+    for previous in read_connections(app)? {
+        for reference in secret_refs(&previous).filter(|r| !kept.contains(r)) {
+            let _ = credentials.remove(reference);
+        }
+    }
+
+    write_connections(app, &connections)
 }
 
-pub fn add_connection(app: &AppHandle, connection: SavedConnection) -> Result<(), String> {
-    let mut connections = load_connections(app)?;
+pub fn add_connection(app: &AppHandle, credentials: &CredentialManager, connection: SavedConnection) -> Result<(), AppError> {
+    let mut connections = load_connections(app, credentials)?;
     // Replace if exists (by id) or add
     if let Some(pos) = connections.iter().position(|c| c.id == connection.id) {
         connections[pos] = connection;
     } else {
         connections.push(connection);
     }
-    save_connections(app, &connections)
+    save_connections(app, credentials, &connections)
 }
 
-pub fn delete_connection(app: &AppHandle, id: &str) -> Result<(), String> {
-    let mut connections = load_connections(app)?;
+pub fn delete_connection(app: &AppHandle, credentials: &CredentialManager, id: &str) -> Result<(), AppError> {
+    let mut connections = load_connections(app, credentials)?;
     connections.retain(|c| c.id != id);
-    save_connections(app, &connections)
+    save_connections(app, credentials, &connections)
+}
+
+fn read_connections(app: &AppHandle) -> Result<Vec<SavedConnection>, AppError> {
+    let path = get_connections_path(app)?;
+    let (file, problem) = read_config::<ConnectionsFile>(&path, CONNECTIONS_MIGRATIONS)?;
+    report_problem(app, problem);
+    Ok(file.map(|f| f.connections).unwrap_or_default())
+}
+
+fn write_connections(app: &AppHandle, connections: &[SavedConnection]) -> Result<(), AppError> {
+    let path = get_connections_path(app)?;
+    let file = ConnectionsFile {
+        version: CONNECTIONS_MIGRATIONS.len() as u32,
+        connections: connections.to_vec(),
+    };
+    write_config(&path, &serde_json::to_string_pretty(&file)?)
+}
+
+fn has_plaintext_secret(connection: &SavedConnection) -> bool {
+    connection.config.password.is_some()
+        || connection
+            .ssh_tunnel
+            .as_ref()
+            .is_some_and(|tunnel| tunnel.password.is_some() || tunnel.passphrase.is_some())
+}
+
+fn secret_refs(connection: &SavedConnection) -> impl Iterator<Item = &str> {
+    let tunnel = connection.ssh_tunnel.as_ref();
+    [
+        connection.config.password_ref.as_deref(),
+        tunnel.and_then(|t| t.password_ref.as_deref()),
+        tunnel.and_then(|t| t.passphrase_ref.as_deref()),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+// Swaps the connection's plaintext secrets (database password, SSH password or key
+// passphrase) for references into the credential store
+fn secure_secrets(credentials: &CredentialManager, connection: &mut SavedConnection) -> Result<(), AppError> {
+    let key = format!("connection:{}", connection.id);
+    secure_secret(credentials, &key, &mut connection.config.password, &mut connection.config.password_ref)?;
+    if let Some(tunnel) = &mut connection.ssh_tunnel {
+        secure_secret(credentials, &format!("{}:ssh-password", key), &mut tunnel.password, &mut tunnel.password_ref)?;
+        secure_secret(credentials, &format!("{}:ssh-passphrase", key), &mut tunnel.passphrase, &mut tunnel.passphrase_ref)?;
+    }
+    Ok(())
+}
+
+fn secure_secret(
+    credentials: &CredentialManager,
+    key: &str,
+    secret: &mut Option<String>,
+    reference: &mut Option<String>,
+) -> Result<(), AppError> {
+    if let Some(plaintext) = secret.take() {
+        match credentials.store(key, &plaintext) {
+            Ok(stored) => *reference = Some(stored),
+            Err(e) => {
+                *secret = Some(plaintext);
+                return Err(e);
+            }
+        }
+    }
+    Ok(())
+}
+
+const CREDENTIALS_FILE_NAME: &str = "credentials.enc.json";
+
+pub fn get_credentials_path(app: &AppHandle) -> Result<PathBuf, AppError> {
+    let mut path = app.path().app_data_dir()?;
+    fs::create_dir_all(&path)?;
+    path.push(CREDENTIALS_FILE_NAME);
+    Ok(path)
+}
+
+const SETTINGS_FILE_NAME: &str = "settings.json";
+
+#[derive(Serialize, Deserialize)]
+struct SettingsFile {
+    version: u32,
+    #[serde(flatten)]
+    settings: AppSettings,
 }
+
+const SETTINGS_MIGRATIONS: &[Migration] = &[];
+
+fn get_settings_path(app: &AppHandle) -> Result<PathBuf, AppError> {
+    let mut path = app.path().app_data_dir()?;
+    fs::create_dir_all(&path)?;
+    path.push(SETTINGS_FILE_NAME);
+    Ok(path)
+}
+
+pub fn load_settings(app: &AppHandle) -> Result<AppSettings, AppError> {
+    let path = get_settings_path(app)?;
+    let (file, problem) = read_config::<SettingsFile>(&path, SETTINGS_MIGRATIONS)?;
+    report_problem(app, problem);
+    Ok(file.map(|f| f.settings).unwrap_or_default())
+}
+
+pub fn save_settings(app: &AppHandle, settings: AppSettings) -> Result<(), AppError> {
+    let path = get_settings_path(app)?;
+    let file = SettingsFile {
+        version: SETTINGS_MIGRATIONS.len() as u32,
+        settings,
+    };
+    write_config(&path, &serde_json::to_string_pretty(&file)?)
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TabState {
     pub id: String,
@@ -56,6 +356,7 @@ pub struct TabState {
     pub connection_id: Option<String>,
     pub saved_connection_id: Option<String>,
     pub db_name: Option<String>,
+    pub variables: Option<HashMap<String, String>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -70,26 +371,318 @@ pub struct Session {
 
 const SESSION_FILE_NAME: &str = "session.json";
 
-fn get_session_path(app: &AppHandle) -> Result<PathBuf, String> {
-    let mut path = app.path().app_data_dir().map_err(|e| e.to_string())?;
-    fs::create_dir_all(&path).map_err(|e| e.to_string())?;
+#[derive(Serialize, Deserialize)]
+struct SessionFile {
+    version: u32,
+    #[serde(flatten)]
+    session: Session,
+}
+
+const SESSION_MIGRATIONS: &[Migration] = &[session_v0_to_v1];
+
+// v0 was the session object without a version field
+fn session_v0_to_v1(value: Value) -> Result<Value, AppError> {
+    match value {
+        Value::Object(mut session) => {
+            session.insert("version".to_string(), json!(1));
+            Ok(Value::Object(session))
+        }
+        _ => Err(AppError::storage("Expected a session object")),
+    }
+}
+
+fn get_session_path(app: &AppHandle) -> Result<PathBuf, AppError> {
+    let mut path = app.path().app_data_dir()?;
+    fs::create_dir_all(&path)?;
     path.push(SESSION_FILE_NAME);
     Ok(path)
 }
 
-pub fn load_session(app: &AppHandle) -> Result<Session, String> {
+pub fn load_session(app: &AppHandle) -> Result<Session, AppError> {
     let path = get_session_path(app)?;
-    if !path.exists() {
-        return Ok(Session::default());
-    }
-    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
-    let session: Session = serde_json::from_str(&content).unwrap_or_default();
-    Ok(session)
+    let (file, problem) = read_config::<SessionFile>(&path, SESSION_MIGRATIONS)?;
+    report_problem(app, problem);
+    Ok(file.map(|f| f.session).unwrap_or_default())
 }
 
-pub fn save_session(app: &AppHandle, session: Session) -> Result<(), String> {
+pub fn save_session(app: &AppHandle, session: Session) -> Result<(), AppError> {
     let path = get_session_path(app)?;
-    let content = serde_json::to_string_pretty(&session).map_err(|e| e.to_string())?;
-    fs::write(path, content).map_err(|e| e.to_string())?;
+    let file = SessionFile {
+        version: SESSION_MIGRATIONS.len() as u32,
+        session,
+    };
+    write_config(&path, &serde_json::to_string_pretty(&file)?)
+}
+
+// One JSON entry per line, appended as queries finish. Once the file reaches
+// MAX_HISTORY_BYTES it becomes history.1.jsonl, replacing the one before, so the history
+// is kept to about twice that.
+const HISTORY_FILE_NAME: &str = "history.jsonl";
+const ROTATED_HISTORY_FILE_NAME: &str = "history.1.jsonl";
+const MAX_HISTORY_BYTES: u64 = 8 * 1024 * 1024;
+const DEFAULT_HISTORY_LIMIT: usize = 200;
+
+// Oldest first: the rotated file, then the current one
+fn get_history_paths(app: &AppHandle) -> Result<[PathBuf; 2], AppError> {
+    let dir = app.path().app_data_dir()?;
+    fs::create_dir_all(&dir)?;
+    Ok([dir.join(ROTATED_HISTORY_FILE_NAME), dir.join(HISTORY_FILE_NAME)])
+}
+
+pub fn append_history(app: &AppHandle, entry: &HistoryEntry) -> Result<(), AppError> {
+    let [rotated, path] = get_history_paths(app)?;
+    let state = app.state::<AppState>();
+    let _guard = state.history_lock.lock()?;
+    if fs::metadata(&path).is_ok_and(|meta| meta.len() >= MAX_HISTORY_BYTES) {
+        fs::rename(&path, rotated)?;
+    }
+
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+
+    // A single write of the whole line keeps entries from concurrent queries apart
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+// Reads the entries one at a time, oldest first. A line that doesn't parse (e.g. cut short
+// by a crash) is skipped.
+fn for_each_history_entry(app: &AppHandle, mut f: impl FnMut(HistoryEntry)) -> Result<(), AppError> {
+    for path in get_history_paths(app)? {
+        let file = match fs::File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        for line in BufReader::new(file).split(b'\n') {
+            if let Ok(entry) = serde_json::from_slice(&line?) {
+                f(entry);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Newest first. Only the newest `limit` matches are held while the files are read.
+pub fn search_history(app: &AppHandle, filter: &HistoryFilter) -> Result<Vec<HistoryEntry>, AppError> {
+    let since = filter.since.as_deref().map(parse_timestamp).transpose()?;
+    let until = filter.until.as_deref().map(parse_timestamp).transpose()?;
+    let text = filter.text.as_deref().map(str::to_lowercase);
+    let limit = filter.limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+
+    let matches = |entry: &HistoryEntry| {
+        if let Some(text) = &text {
+            if !entry.sql.to_lowercase().contains(text.as_str()) {
+                return false;
+            }
+        }
+        if filter.connection_id.as_ref().is_some_and(|id| *id != entry.connection_id) {
+            return false;
+        }
+        if filter.saved_connection_id.is_some() && filter.saved_connection_id != entry.saved_connection_id {
+            return false;
+        }
+        if filter.status.is_some_and(|status| status != entry.status) {
+            return false;
+        }
+        if since.is_some() || until.is_some() {
+            let Ok(executed_at) = parse_timestamp(&entry.executed_at) else {
+                return false;
+            };
+            if since.is_some_and(|since| executed_at < since) || until.is_some_and(|until| executed_at > until) {
+                return false;
+            }
+        }
+        true
+    };
+
+    let mut newest = VecDeque::with_capacity(limit.min(DEFAULT_HISTORY_LIMIT) + 1);
+    for_each_history_entry(app, |entry| {
+        if matches(&entry) {
+            newest.push_back(entry);
+            if newest.len() > limit {
+                newest.pop_front();
+            }
+        }
+    })?;
+    Ok(newest.into_iter().rev().collect())
+}
+
+pub fn get_history_entry(app: &AppHandle, id: &str) -> Result<HistoryEntry, AppError> {
+    let mut found = None;
+    for_each_history_entry(app, |entry| {
+        if entry.id == id {
+            found = Some(entry);
+        }
+    })?;
+    found.ok_or_else(|| AppError::not_found("History entry not found"))
+}
+
+// Rewrites only the file holding the entry. History is never backed up, and copies kept by
+// older versions are removed too.
+pub fn delete_history_entry(app: &AppHandle, id: &str) -> Result<(), AppError> {
+    #[derive(Deserialize)]
+    struct EntryId {
+        id: String,
+    }
+
+    let state = app.state::<AppState>();
+    let _guard = state.history_lock.lock()?;
+    for path in get_history_paths(app)? {
+        remove_backups(&path)?;
+        if !path.exists() {
+            continue;
+        }
+        let content = fs::read_to_string(&path)?;
+        let is_entry = |line: &str| serde_json::from_str::<EntryId>(line).is_ok_and(|entry| entry.id == id);
+        if !content.lines().any(is_entry) {
+            continue;
+        }
+
+        let mut kept = String::with_capacity(content.len());
+        for line in content.lines().filter(|line| !is_entry(line)) {
+            kept.push_str(line);
+            kept.push('\n');
+        }
+        write_atomic(&path, &kept)?;
+    }
     Ok(())
 }
+
+pub fn clear_history(app: &AppHandle) -> Result<(), AppError> {
+    let state = app.state::<AppState>();
+    let _guard = state.history_lock.lock()?;
+    for path in get_history_paths(app)? {
+        remove_backups(&path)?;
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+fn parse_timestamp(value: &str) -> Result<DateTime<FixedOffset>, AppError> {
+    DateTime::parse_from_rfc3339(value).map_err(|e| AppError::other(format!("Invalid timestamp \"{}\": {}", value, e)))
+}
+
+const SNIPPETS_FILE_NAME: &str = "snippets.json";
+
+#[derive(Serialize, Deserialize)]
+struct SnippetsFile {
+    version: u32,
+    snippets: Vec<SavedQuery>,
+}
+
+const SNIPPETS_MIGRATIONS: &[Migration] = &[snippets_v0_to_v1];
+
+// v0 was a bare array of snippets
+fn snippets_v0_to_v1(value: Value) -> Result<Value, AppError> {
+    match value {
+        Value::Array(snippets) => Ok(json!({ "version": 1, "snippets": snippets })),
+        _ => Err(AppError::storage("Expected a list of snippets")),
+    }
+}
+
+fn get_snippets_path(app: &AppHandle) -> Result<PathBuf, AppError> {
+    let mut path = app.path().app_data_dir()?;
+    fs::create_dir_all(&path)?;
+    path.push(SNIPPETS_FILE_NAME);
+    Ok(path)
+}
+
+pub fn load_snippets(app: &AppHandle) -> Result<Vec<SavedQuery>, AppError> {
+    let path = get_snippets_path(app)?;
+    let (file, problem) = read_config::<SnippetsFile>(&path, SNIPPETS_MIGRATIONS)?;
+    report_problem(app, problem);
+    Ok(file.map(|f| f.snippets).unwrap_or_default())
+}
+
+fn save_snippets(app: &AppHandle, snippets: &[SavedQuery]) -> Result<(), AppError> {
+    let path = get_snippets_path(app)?;
+    let file = SnippetsFile {
+        version: SNIPPETS_MIGRATIONS.len() as u32,
+        snippets: snippets.to_vec(),
+    };
+    write_config(&path, &serde_json::to_string_pretty(&file)?)
+}
+
+// Inserts or replaces by id, returning the snippet as stored
+pub fn add_snippet(app: &AppHandle, mut snippet: SavedQuery) -> Result<SavedQuery, AppError> {
+    let mut snippets = load_snippets(app)?;
+    let now = Utc::now().to_rfc3339();
+    snippet.updated_at = now.clone();
+
+    if let Some(pos) = snippets.iter().position(|s| s.id == snippet.id) {
+        snippet.created_at = snippets[pos].created_at.clone();
+        snippets[pos] = snippet.clone();
+    } else {
+        snippet.created_at = now;
+        snippets.push(snippet.clone());
+    }
+    save_snippets(app, &snippets)?;
+    Ok(snippet)
+}
+
+pub fn delete_snippet(app: &AppHandle, id: &str) -> Result<(), AppError> {
+    let mut snippets = load_snippets(app)?;
+    snippets.retain(|s| s.id != id);
+    save_snippets(app, &snippets)
+}
+
+// Every word of `text` must appear somewhere in the snippet. Matches in the name count for
+// most, then tags, then folder and description, then the SQL; best matches come first.
+pub fn search_snippets(
+    app: &AppHandle,
+    text: &str,
+    folder: Option<&str>,
+    tag: Option<&str>,
+) -> Result<Vec<SavedQuery>, AppError> {
+    let words: Vec<String> = text.split_whitespace().map(str::to_lowercase).collect();
+
+    let mut scored: Vec<(usize, SavedQuery)> = load_snippets(app)?
+        .into_iter()
+        .filter(|s| folder.is_none_or(|folder| in_folder(s.folder.as_deref(), folder)))
+        .filter(|s| tag.is_none_or(|tag| s.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))))
+        .filter_map(|s| snippet_score(&s, &words).map(|score| (score, s)))
+        .collect();
+
+    scored.sort_by(|(a_score, a), (b_score, b)| b_score.cmp(a_score).then_with(|| a.name.cmp(&b.name)));
+    Ok(scored.into_iter().map(|(_, s)| s).collect())
+}
+
+// The folder itself or any folder below it
+fn in_folder(snippet_folder: Option<&str>, folder: &str) -> bool {
+    let folder = folder.trim_matches('/');
+    let snippet_folder = snippet_folder.unwrap_or("").trim_matches('/');
+    folder.is_empty()
+        || snippet_folder == folder
+        || snippet_folder.strip_prefix(folder).is_some_and(|rest| rest.starts_with('/'))
+}
+
+fn snippet_score(snippet: &SavedQuery, words: &[String]) -> Option<usize> {
+    let name = snippet.name.to_lowercase();
+    let tags: Vec<String> = snippet.tags.iter().map(|t| t.to_lowercase()).collect();
+    let other = format!(
+        "{} {}",
+        snippet.folder.as_deref().unwrap_or(""),
+        snippet.description.as_deref().unwrap_or("")
+    )
+    .to_lowercase();
+    let sql = snippet.sql.to_lowercase();
+
+    words.iter().try_fold(0, |score, word| {
+        let word_score = if name.contains(word.as_str()) {
+            8
+        } else if tags.iter().any(|t| t.contains(word.as_str())) {
+            4
+        } else if other.contains(word.as_str()) {
+            2
+        } else if sql.contains(word.as_str()) {
+            1
+        } else {
+            return None;
+        };
+        Some(score + word_score)
+    })
+}
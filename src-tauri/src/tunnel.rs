@@ -0,0 +1,272 @@
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use russh::client::{self, AuthResult, Handle};
+use russh::keys::known_hosts::{check_known_hosts, check_known_hosts_path, learn_known_hosts, learn_known_hosts_path};
+use russh::keys::{load_secret_key, ssh_key, HashAlg, PrivateKeyWithHashAlg, PublicKey, PublicKeyOrCertificate};
+use russh::Disconnect;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::task::JoinHandle;
+
+use crate::error::AppError;
+use crate::models::{DbConfig, SshTunnelConfig};
+
+// Jump hosts commonly drop idle sessions, which would strand the pooled connections
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+// A logged-in SSH session plus a local listener. Each connection made to the listener is
+// forwarded through the session to the database host. Where there are Unix sockets the
+// listener is one, in a private directory, so the driver can connect to it while still
+// verifying TLS against the database host's name; elsewhere it's a loopback port.
+pub struct SshTunnel {
+    #[cfg(unix)]
+    socket_dir: PathBuf,
+    #[cfg(not(unix))]
+    local_port: u16,
+    session: Arc<Handle<HostKeyCheck>>,
+    listener: JoinHandle<()>,
+}
+
+impl SshTunnel {
+    // `target_host` and `target_port` are resolved by the jump host, not locally
+    pub async fn open(config: &SshTunnelConfig, target_host: &str, target_port: u16) -> Result<Self, AppError> {
+        let rejection = Arc::new(Mutex::new(None));
+        let handler = HostKeyCheck {
+            host: config.host.clone(),
+            port: config.port,
+            known_hosts_path: config.known_hosts_path.clone(),
+            accept_new: config.accept_new_host_key,
+            rejection: rejection.clone(),
+        };
+        let client_config = Arc::new(client::Config {
+            keepalive_interval: Some(KEEPALIVE_INTERVAL),
+            ..Default::default()
+        });
+
+        let mut session = match client::connect(client_config, (config.host.as_str(), config.port), handler).await {
+            Ok(session) => session,
+            Err(e) => {
+                // A rejected host key only shows up as a generic error; report why it was rejected
+                let reason = rejection.lock()?.take();
+                return Err(tunnel_error(reason.unwrap_or_else(|| format!("Can't connect to {}:{}: {}", config.host, config.port, e))));
+            }
+        };
+        authenticate(&mut session, config).await?;
+
+        let session = Arc::new(session);
+        let target = Target { session: session.clone(), host: target_host.to_string(), port: target_port };
+
+        #[cfg(unix)]
+        {
+            let socket_dir = socket_dir()?;
+            // Named as the driver looks for it: `.s.PGSQL.<port>` inside the directory
+            let listener = tokio::net::UnixListener::bind(socket_dir.join(format!(".s.PGSQL.{}", target_port)))
+                .map_err(|e| tunnel_error(format!("Can't open a local socket: {}", e)))?;
+            let listener = tokio::spawn(async move {
+                while let Ok((socket, _)) = listener.accept().await {
+                    target.forward(socket, "127.0.0.1", 0);
+                }
+            });
+            Ok(Self { socket_dir, session, listener })
+        }
+
+        #[cfg(not(unix))]
+        {
+            let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+                .await
+                .map_err(|e| tunnel_error(format!("Can't open a local port: {}", e)))?;
+            let local_port = listener
+                .local_addr()
+                .map_err(|e| tunnel_error(e.to_string()))?
+                .port();
+            let listener = tokio::spawn(async move {
+                while let Ok((socket, peer)) = listener.accept().await {
+                    target.forward(socket, &peer.ip().to_string(), peer.port());
+                }
+            });
+            Ok(Self { local_port, session, listener })
+        }
+    }
+
+    // Points the connection at the tunnel's local end. The host and port are left as
+    // configured where the tunnel is a socket, since TLS verifies the server against them.
+    pub fn route(&self, config: &mut DbConfig) -> Result<(), AppError> {
+        #[cfg(unix)]
+        {
+            config.tunnel_socket = Some(self.socket_dir.clone());
+        }
+        #[cfg(not(unix))]
+        {
+            if config.ssl_mode == crate::models::SslMode::VerifyFull {
+                return Err(tunnel_error("verify-full can't check the host name through a tunnel on this platform"));
+            }
+            config.host = "127.0.0.1".to_string();
+            config.port = self.local_port;
+        }
+        Ok(())
+    }
+
+    pub async fn close(&self) {
+        self.listener.abort();
+        let _ = self.session.disconnect(Disconnect::ByApplication, "", "en").await;
+        self.remove_socket();
+    }
+
+    fn remove_socket(&self) {
+        #[cfg(unix)]
+        let _ = std::fs::remove_dir_all(&self.socket_dir);
+    }
+}
+
+impl Drop for SshTunnel {
+    fn drop(&mut self) {
+        self.listener.abort();
+        self.remove_socket();
+    }
+}
+
+// Where forwarded connections go, as seen from the jump host
+struct Target {
+    session: Arc<Handle<HostKeyCheck>>,
+    host: String,
+    port: u16,
+}
+
+impl Target {
+    fn forward<S>(&self, mut socket: S, originator_host: &str, originator_port: u16)
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let session = self.session.clone();
+        let (host, port, originator_host) = (self.host.clone(), self.port, originator_host.to_string());
+        tokio::spawn(async move {
+            // On failure the socket is dropped and the driver sees the connection close
+            let Ok(channel) = session
+                .channel_open_direct_tcpip(host, port.into(), originator_host, originator_port.into())
+                .await
+            else {
+                return;
+            };
+            let mut stream = channel.into_stream();
+            let _ = tokio::io::copy_bidirectional(&mut socket, &mut stream).await;
+        });
+    }
+}
+
+// A fresh directory only the current user can enter, kept short since socket paths are
+// limited to about 100 bytes
+#[cfg(unix)]
+fn socket_dir() -> Result<PathBuf, AppError> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    let id = uuid::Uuid::new_v4().simple().to_string();
+    let dir = std::env::temp_dir().join(format!("pgmac-{}", &id[..12]));
+    std::fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&dir)
+        .map_err(|e| tunnel_error(format!("Can't create a directory for the local socket: {}", e)))?;
+    Ok(dir)
+}
+
+// Tries the private key when one is configured, otherwise the password
+async fn authenticate(session: &mut Handle<HostKeyCheck>, config: &SshTunnelConfig) -> Result<(), AppError> {
+    let result = if let Some(key_path) = &config.private_key_path {
+        let key = load_secret_key(key_path, config.passphrase.as_deref()).map_err(|e| {
+            let reason = match e {
+                russh::keys::Error::KeyIsEncrypted => "it needs a passphrase".to_string(),
+                // Decrypting with the wrong passphrase fails the integrity check
+                russh::keys::Error::SshKey(ssh_key::Error::Crypto) => "the passphrase is wrong".to_string(),
+                e => e.to_string(),
+            };
+            tunnel_error(format!("Can't load the private key \"{}\": {}", key_path, reason))
+        })?;
+        // RSA keys sign with the best hash the server accepts; other key types ignore it
+        let hash = session.best_supported_rsa_hash().await.map_err(ssh_error)?.flatten();
+        session
+            .authenticate_publickey(&config.user, PrivateKeyWithHashAlg::new(Arc::new(key), hash))
+            .await
+    } else if let Some(password) = &config.password {
+        session.authenticate_password(&config.user, password).await
+    } else {
+        return Err(tunnel_error("Give a password or a private key for the SSH login"));
+    };
+
+    match result.map_err(ssh_error)? {
+        AuthResult::Success => Ok(()),
+        AuthResult::Failure { .. } => Err(tunnel_error(format!(
+            "Authentication as \"{}\" on {} was rejected",
+            config.user, config.host
+        ))),
+    }
+}
+
+// Verifies the server's host key against known_hosts, like OpenSSH's StrictHostKeyChecking.
+// With `accept_new` an unknown host is recorded on first use; a changed key is always refused.
+struct HostKeyCheck {
+    host: String,
+    port: u16,
+    known_hosts_path: Option<String>,
+    accept_new: bool,
+    rejection: Arc<Mutex<Option<String>>>,
+}
+
+impl HostKeyCheck {
+    fn reject(&self, reason: String) -> Result<bool, russh::Error> {
+        if let Ok(mut rejection) = self.rejection.lock() {
+            *rejection = Some(reason);
+        }
+        Ok(false)
+    }
+
+    fn is_known(&self, key: &PublicKey) -> Result<bool, russh::keys::Error> {
+        match &self.known_hosts_path {
+            Some(path) => check_known_hosts_path(&self.host, self.port, key, path),
+            None => check_known_hosts(&self.host, self.port, key),
+        }
+    }
+
+    fn learn(&self, key: &PublicKey) -> Result<(), russh::keys::Error> {
+        match &self.known_hosts_path {
+            Some(path) => learn_known_hosts_path(&self.host, self.port, key, path),
+            None => learn_known_hosts(&self.host, self.port, key),
+        }
+    }
+}
+
+impl client::Handler for HostKeyCheck {
+    type Error = russh::Error;
+
+    async fn check_server_key(&mut self, server_key: &PublicKeyOrCertificate) -> Result<bool, Self::Error> {
+        let PublicKeyOrCertificate::PublicKey { key, .. } = server_key else {
+            return self.reject(format!("{} presented a host certificate, which isn't supported", self.host));
+        };
+        let fingerprint = key.fingerprint(HashAlg::Sha256);
+
+        match self.is_known(key) {
+            Ok(true) => Ok(true),
+            Ok(false) if self.accept_new => match self.learn(key) {
+                Ok(()) => Ok(true),
+                Err(e) => self.reject(format!("Can't record the host key of {} in known_hosts: {}", self.host, e)),
+            },
+            Ok(false) => self.reject(format!(
+                "The host key of {} ({}) isn't in known_hosts. Add it there or allow new host keys for this connection.",
+                self.host, fingerprint
+            )),
+            Err(russh::keys::Error::KeyChanged { line }) => self.reject(format!(
+                "The host key of {} has changed from the one on line {} of known_hosts (now {}). This could be an attack; update known_hosts only if the change is expected.",
+                self.host, line, fingerprint
+            )),
+            Err(e) => self.reject(format!("Can't read known_hosts: {}", e)),
+        }
+    }
+}
+
+fn ssh_error(err: russh::Error) -> AppError {
+    tunnel_error(err.to_string())
+}
+
+fn tunnel_error(message: impl std::fmt::Display) -> AppError {
+    AppError::connection(format!("SSH tunnel: {}", message))
+}
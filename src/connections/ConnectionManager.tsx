@@ -2,6 +2,7 @@ import { useState } from "react";
 import { invoke } from "@tauri-apps/api/core";
 import { useNavigate } from "react-router-dom";
 import clsx from "clsx";
+import { formatError, withCredentials } from "../pages/helpers";
 
 export function ConnectionManager() {
   const navigate = useNavigate();
@@ -32,7 +33,7 @@ export function ConnectionManager() {
       // Navigate to workspace with this connection active
       navigate("/workspace", { state: { connectionId, dbName: formData.name } });
     } catch (err) {
-      setError(String(err));
+      setError(formatError(err));
     } finally {
       setLoading(false);
     }
@@ -43,16 +44,18 @@ export function ConnectionManager() {
     setLoading(true);
     try {
       const id = crypto.randomUUID();
-      await invoke("save_connection", {
-        connection: {
-          id,
-          name: formData.name,
-          config: {
-            ...formData,
-            password: formData.password || null,
+      await withCredentials(() =>
+        invoke("save_connection", {
+          connection: {
+            id,
+            name: formData.name,
+            config: {
+              ...formData,
+              password: formData.password || null,
+            },
           },
-        },
-      });
+        })
+      );
       // Also connect
       const connectionId = await invoke<string>("connect_db", {
         config: {
@@ -64,7 +67,7 @@ export function ConnectionManager() {
       // Navigate to workspace with this connection active
       navigate("/workspace", { state: { connectionId, dbName: formData.name } });
     } catch (err) {
-      setError(String(err));
+      setError(formatError(err));
     } finally {
       setLoading(false);
     }
@@ -9,7 +9,7 @@ import { useAppStore } from "../store/useAppStore";
 import { ErrorModal } from "../components/ErrorModal";
 import { generatePreviewSql } from "../results/helpers";
 import { QueryResult, ColumnDefinition, Session, SavedConnection, WorkspaceTab } from "./type";
-import { maybeQuoteIdentifier } from "./helpers";
+import { connectArgs, formatError, maybeQuoteIdentifier, pickPrimaryResult, withCredentials } from "./helpers";
 import DeleteConfirmModal from "../components/DeleteConfirmModal";
 
 export function Workspace() {
@@ -82,6 +82,8 @@ export function Workspace() {
 
     const newTabs = tabs.filter((t) => t.id !== id);
     setTabs(newTabs);
+    // Frees the tab's open result cursors and pinned session on the backend
+    invoke("close_tab", { tabId: id }).catch(console.error);
     if (activeTabId === id) {
       setActiveTabId(newTabs[newTabs.length - 1].id);
     }
@@ -106,10 +108,13 @@ export function Workspace() {
     const startTime = performance.now();
 
     try {
-      const data = await invoke<QueryResult>("run_query", {
+      const resultSets = await invoke<QueryResult[]>("run_query", {
         connectionId: targetConnectionId,
         query: queryToRun,
+        queryId: activeTabRef.current.id,
+        sessionId: activeTabRef.current.id,
       });
+      const data = pickPrimaryResult(resultSets);
 
       const duration = performance.now() - startTime;
 
@@ -160,14 +165,15 @@ export function Workspace() {
 
       updateActiveTab({
         results: data,
+        resultSets,
         title: newTitle,
         executionDurationMs: duration,
         selectedTable: inferredSelectedTable,
         columnDefs: inferredColumnDefs,
       });
     } catch (err) {
-      updateActiveTab({ error: String(err) });
-      setErrorModal({ isOpen: true, error: String(err), sql: queryToRun });
+      updateActiveTab({ error: formatError(err) });
+      setErrorModal({ isOpen: true, error: formatError(err), sql: queryToRun });
     } finally {
       updateActiveTab({ isLoading: false });
     }
@@ -198,7 +204,7 @@ export function Workspace() {
           tabs: simpleTabs,
           active_tab_id: activeId,
         },
-      }).catch((e) => alert("Save failed: " + String(e)));
+      }).catch((e) => alert("Save failed: " + formatError(e)));
     },
     [],
   );
@@ -250,14 +256,14 @@ export function Workspace() {
       });
 
       // Fetch metadata and results
-      const res = await invoke<QueryResult>("run_query", {
+      const resultSets = await invoke<QueryResult[]>("run_query", {
         connectionId,
         query: newSql,
       });
 
-      updateActiveTab({ columnDefs: cols, results: res, isLoading: false });
+      updateActiveTab({ columnDefs: cols, results: pickPrimaryResult(resultSets), resultSets, isLoading: false });
     } catch (err) {
-      updateActiveTab({ error: String(err), isLoading: false });
+      updateActiveTab({ error: formatError(err), isLoading: false });
     }
   };
 
@@ -283,7 +289,7 @@ export function Workspace() {
           const config = savedConns.find((c) => c.id === savedId);
           if (config) {
             try {
-              const liveId = await invoke<string>("connect_db", { config: config.config });
+              const liveId = await withCredentials(() => invoke<string>("connect_db", connectArgs(config)));
               savedToLiveMap.set(savedId, liveId);
             } catch (err) {
               console.error(`Failed to restore connection ${savedId}`, err);
@@ -362,7 +368,7 @@ export function Workspace() {
       } catch (e) {
         console.error("Failed to load session", e);
         // Alert helpful for prod debugging if console hidden
-        alert("Failed to load session: " + String(e));
+        alert("Failed to load session: " + formatError(e));
         // Do NOT set isSessionLoaded(true) if critical failure to avoid overwriting with empty state
         return;
       } finally {
@@ -443,7 +449,7 @@ export function Workspace() {
     } catch (err) {
       console.error("Update failed", err);
       const generatedSql = generatePreviewSql(currentTab.selectedTable, column, newValue, originalRow, columns, currentTab.columnDefs);
-      setErrorModal({ isOpen: true, error: String(err), sql: generatedSql });
+      setErrorModal({ isOpen: true, error: formatError(err), sql: generatedSql });
       throw err;
     }
   }, []); // Empty dependency! stable for memoization. Relies on refs.
@@ -515,7 +521,7 @@ export function Workspace() {
       // Refresh data
       runQuery(activeTab.sql);
     } catch (err) {
-      setErrorModal({ isOpen: true, error: String(err), sql: deleteConfirm.sql });
+      setErrorModal({ isOpen: true, error: formatError(err), sql: deleteConfirm.sql });
     } finally {
       setIsDeleting(false);
     }
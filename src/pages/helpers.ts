@@ -1,3 +1,54 @@
+import { invoke } from "@tauri-apps/api/core";
+import { AppError, QueryResult, SavedConnection } from "./type";
+
+// Commands reject with a structured AppError; flatten it for display
+export const formatError = (err: unknown): string => {
+  if (err && typeof err === "object" && "message" in err) {
+    const e = err as AppError;
+    const lines = [e.severity ? `${e.severity}: ${e.message}` : e.message];
+    if (e.detail) lines.push(`DETAIL: ${e.detail}`);
+    if (e.hint) lines.push(`HINT: ${e.hint}`);
+    if (e.code) lines.push(`SQLSTATE: ${e.code}`);
+    return lines.join("\n");
+  }
+  return String(err);
+};
+
+// Arguments for connect_db, so every way of opening a saved connection brings its tunnel,
+// pool, limits and variables along
+export const connectArgs = (saved: SavedConnection) => ({
+  config: saved.config,
+  variables: saved.variables ?? null,
+  limits: saved.limits ?? null,
+  savedConnectionId: saved.id,
+  connectionName: saved.name,
+  sshTunnel: saved.ssh_tunnel ?? null,
+  pool: saved.pool ?? null,
+});
+
+// Runs a command that reads or stores saved passwords. Without an OS keyring they live in the
+// encrypted credential file, so a locked file prompts for the master password and the
+// command is tried once more.
+export const withCredentials = async <T>(run: () => Promise<T>): Promise<T> => {
+  try {
+    return await run();
+  } catch (err) {
+    if ((err as AppError | null)?.kind !== "credentials_locked") throw err;
+    const masterPassword = window.prompt(
+      "The OS keyring isn't available, so passwords are kept in an encrypted file. Enter its master password (a new file is created with it on first use):"
+    );
+    if (!masterPassword) throw err;
+    await invoke("unlock_credentials", { masterPassword });
+    return run();
+  }
+};
+
+// A script returns one result per statement; show the last one that produced rows
+export const pickPrimaryResult = (results: QueryResult[]): QueryResult | null => {
+  const withColumns = results.filter((r) => r.columns.length > 0);
+  return withColumns[withColumns.length - 1] ?? results[results.length - 1] ?? null;
+};
+
 export const maybeQuoteIdentifier = (name: string): string => {
   const needsQuotes =
     /[A-Z]/.test(name) || // Has caps
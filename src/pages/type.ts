@@ -3,6 +3,15 @@ export interface QueryResult {
   rows: any[][];
   affected_rows: number;
   query_type: string;
+  command_tag: string;
+  duration_ms: number;
+  span: { start: number; end: number } | null;
+  notices: { severity: string; message: string; timestamp: string }[];
+  cancelled: boolean;
+  result_id: string | null;
+  has_more: boolean;
+  expanded: boolean;
+  truncated: boolean;
 }
 
 export interface ColumnDefinition {
@@ -34,12 +43,52 @@ export interface DbConfig {
   port: number;
   user: string;
   dbname: string;
+  password_ref?: string | null;
+  service?: string | null;
+  ssl_mode?: SslMode;
+  ssl_root_cert?: string | null;
+  ssl_client_cert?: string | null;
+  ssl_client_key?: string | null;
+  parameters?: Record<string, string>;
+  session?: SessionOptions;
 }
 
 export interface SavedConnection {
   id: string;
   name: string;
   config: DbConfig;
+  variables?: Record<string, string> | null;
+  limits?: ExecutionLimits | null;
+  ssh_tunnel?: SshTunnelConfig | null;
+  pool?: PoolSettings | null;
+}
+
+export interface ImportedConnection extends SavedConnection {
+  warnings: string[];
+}
+
+export interface ExecutionLimits {
+  statement_timeout_ms?: number | null;
+  lock_timeout_ms?: number | null;
+  max_rows?: number | null;
+}
+
+export interface PoolSettings {
+  max_connections?: number | null;
+  min_connections?: number | null;
+  acquire_timeout_secs?: number | null;
+  idle_timeout_secs?: number | null;
+  max_lifetime_secs?: number | null;
+  max_pinned_sessions?: number | null;
+}
+
+export interface PoolStatus {
+  size: number;
+  idle: number;
+  in_use: number;
+  max_connections: number;
+  pinned: number;
+  max_pinned: number;
 }
 
 export interface WorkspaceTab {
@@ -49,6 +98,7 @@ export interface WorkspaceTab {
   title: string;
   sql: string;
   results: QueryResult | null;
+  resultSets?: QueryResult[];
   error: string | null;
   isLoading: boolean;
   selectedTable: string | null;
@@ -56,3 +106,201 @@ export interface WorkspaceTab {
   columnDefs: ColumnDefinition[];
   executionDurationMs?: number;
 }
+
+export interface AppError {
+  kind: "database" | "connection" | "not_found" | "storage" | "credentials" | "credentials_locked" | "other";
+  message: string;
+  code?: string;
+  severity?: string;
+  detail?: string | null;
+  hint?: string | null;
+  position?: number | null;
+}
+
+export type QueryParam =
+  | { type: "text"; value: string }
+  | { type: "number"; value: number }
+  | { type: "bool"; value: boolean }
+  | { type: "null" }
+  | { type: "json"; value: unknown }
+  | { type: "text_array"; value: (string | null)[] }
+  | { type: "number_array"; value: (number | null)[] }
+  | { type: "bool_array"; value: (boolean | null)[] };
+
+export interface StatementDescription {
+  parameters: string[];
+  columns: { name: string; data_type: string }[];
+}
+
+export interface ExplainOptions {
+  analyze?: boolean;
+  buffers?: boolean;
+  verbose?: boolean;
+}
+
+export interface PlanBuffers {
+  shared_hit: number;
+  shared_read: number;
+  shared_dirtied: number;
+  shared_written: number;
+  local_hit: number;
+  local_read: number;
+  temp_read: number;
+  temp_written: number;
+}
+
+export interface PlanNode {
+  node_type: string;
+  parent_relationship: string | null;
+  relation_name: string | null;
+  schema: string | null;
+  alias: string | null;
+  index_name: string | null;
+  join_type: string | null;
+  filter: string | null;
+  index_cond: string | null;
+  hash_cond: string | null;
+  startup_cost: number;
+  total_cost: number;
+  plan_rows: number;
+  plan_width: number;
+  actual_startup_time_ms: number | null;
+  actual_total_time_ms: number | null;
+  actual_rows: number | null;
+  actual_loops: number | null;
+  rows_removed_by_filter: number | null;
+  buffers: PlanBuffers | null;
+  output: string[];
+  exclusive_time_ms: number | null;
+  row_estimate_ratio: number | null;
+  children: PlanNode[];
+}
+
+export interface QueryPlan {
+  root: PlanNode;
+  planning_time_ms: number | null;
+  execution_time_ms: number | null;
+  analyzed: boolean;
+  rolled_back: boolean;
+  raw: unknown;
+}
+
+export interface StatementInfo {
+  span: { start: number; end: number };
+  command: string;
+  kind: "query" | "dml" | "ddl" | "transaction" | "session" | "meta" | "other";
+}
+
+export type ErrorPolicy = "stop" | "continue" | "rollback";
+
+export interface StatementReport {
+  index: number;
+  span: { start: number; end: number };
+  command: string;
+  status: "succeeded" | "failed" | "cancelled" | "skipped" | "rolled_back";
+  affected_rows: number;
+  duration_ms: number;
+  result: QueryResult | null;
+  error: AppError | null;
+}
+
+export interface ScriptReport {
+  policy: ErrorPolicy;
+  statements: StatementReport[];
+  succeeded: number;
+  failed: number;
+  skipped: number;
+  rolled_back: boolean;
+  error: AppError | null;
+  duration_ms: number;
+}
+
+export type HistoryStatus = "succeeded" | "failed" | "cancelled";
+
+export interface HistoryEntry {
+  id: string;
+  sql: string;
+  connection_id: string;
+  saved_connection_id: string | null;
+  connection_name: string | null;
+  database: string | null;
+  session_id: string | null;
+  executed_at: string;
+  duration_ms: number;
+  row_count: number;
+  affected_rows: number;
+  status: HistoryStatus;
+  error: string | null;
+}
+
+export interface HistoryFilter {
+  text?: string | null;
+  connection_id?: string | null;
+  saved_connection_id?: string | null;
+  since?: string | null;
+  until?: string | null;
+  status?: HistoryStatus | null;
+  limit?: number | null;
+}
+
+export interface SnippetParameter {
+  name: string;
+  data_type: string | null;
+  default_value: string | null;
+  description: string | null;
+}
+
+export interface SavedQuery {
+  id: string;
+  name: string;
+  sql: string;
+  description: string | null;
+  tags: string[];
+  folder: string | null;
+  default_connection_id: string | null;
+  parameters: SnippetParameter[];
+  created_at: string;
+  updated_at: string;
+}
+
+export type CredentialBackend = "keyring" | "encrypted_file";
+
+export interface CredentialStatus {
+  preferred: CredentialBackend;
+  keyring_available: boolean;
+  file_exists: boolean;
+  file_unlocked: boolean;
+}
+
+export interface ConfigProblem {
+  file: string;
+  error: string;
+  preserved_as: string;
+  restored_from: string | null;
+}
+
+export type ConnectionStringFormat = "uri" | "key_value";
+
+export type SslMode = "disable" | "prefer" | "require" | "verify-ca" | "verify-full";
+
+export interface SshTunnelConfig {
+  host: string;
+  port: number;
+  user: string;
+  password?: string | null;
+  password_ref?: string | null;
+  private_key_path?: string | null;
+  passphrase?: string | null;
+  passphrase_ref?: string | null;
+  known_hosts_path?: string | null;
+  accept_new_host_key?: boolean;
+}
+
+export interface SessionOptions {
+  application_name?: string | null;
+  search_path?: string | null;
+  role?: string | null;
+  timezone?: string | null;
+  statement_timeout_ms?: number | null;
+  settings?: Record<string, string>;
+}
@@ -3,6 +3,15 @@ export interface QueryResult {
   rows: any[][];
   affected_rows: number;
   query_type: string;
+  command_tag: string;
+  duration_ms: number;
+  span: { start: number; end: number } | null;
+  notices: { severity: string; message: string; timestamp: string }[];
+  cancelled: boolean;
+  result_id: string | null;
+  has_more: boolean;
+  expanded: boolean;
+  truncated: boolean;
 }
 
 export interface ColumnDefinition {
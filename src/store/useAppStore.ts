@@ -1,5 +1,30 @@
 import { create } from "zustand";
 import { invoke } from "@tauri-apps/api/core";
+import { connectArgs, withCredentials } from "../pages/helpers";
+
+export type SslMode = "disable" | "prefer" | "require" | "verify-ca" | "verify-full";
+
+export interface SshTunnelConfig {
+  host: string;
+  port: number;
+  user: string;
+  password?: string | null;
+  password_ref?: string | null;
+  private_key_path?: string | null;
+  passphrase?: string | null;
+  passphrase_ref?: string | null;
+  known_hosts_path?: string | null;
+  accept_new_host_key?: boolean;
+}
+
+export interface SessionOptions {
+  application_name?: string | null;
+  search_path?: string | null;
+  role?: string | null;
+  timezone?: string | null;
+  statement_timeout_ms?: number | null;
+  settings?: Record<string, string>;
+}
 
 export interface DbConfig {
   host: string;
@@ -7,12 +32,39 @@ export interface DbConfig {
   user: string;
   dbname: string;
   password?: string;
+  password_ref?: string | null;
+  service?: string | null;
+  ssl_mode?: SslMode;
+  ssl_root_cert?: string | null;
+  ssl_client_cert?: string | null;
+  ssl_client_key?: string | null;
+  parameters?: Record<string, string>;
+  session?: SessionOptions;
 }
 
 export interface SavedConnection {
   id: string;
   name: string;
   config: DbConfig;
+  variables?: Record<string, string> | null;
+  limits?: ExecutionLimits | null;
+  ssh_tunnel?: SshTunnelConfig | null;
+  pool?: PoolSettings | null;
+}
+
+export interface ExecutionLimits {
+  statement_timeout_ms?: number | null;
+  lock_timeout_ms?: number | null;
+  max_rows?: number | null;
+}
+
+export interface PoolSettings {
+  max_connections?: number | null;
+  min_connections?: number | null;
+  acquire_timeout_secs?: number | null;
+  idle_timeout_secs?: number | null;
+  max_lifetime_secs?: number | null;
+  max_pinned_sessions?: number | null;
 }
 
 export interface SchemaNode {
@@ -100,7 +152,7 @@ export const useAppStore = create<AppStore>((set, get) => ({
     });
 
     try {
-      const liveId = await invoke<string>("connect_db", { config: node.data.config });
+      const liveId = await withCredentials(() => invoke<string>("connect_db", connectArgs(node.data)));
       const schemas = await invoke<string[]>("get_schemas", { connectionId: liveId });
 
       set((state) => {
@@ -292,7 +344,7 @@ export const useAppStore = create<AppStore>((set, get) => ({
     });
 
     try {
-      await invoke("update_connections_list", { connections: newOrder });
+      await withCredentials(() => invoke("update_connections_list", { connections: newOrder }));
     } catch (err) {
       console.error("Failed to reorder connections", err);
       get().loadConnections();
@@ -317,7 +369,7 @@ export const useAppStore = create<AppStore>((set, get) => ({
     try {
       const updatedConnection = connections[nodeIndex].data;
       updatedConnection.name = newName;
-      await invoke("save_connection", { connection: updatedConnection });
+      await withCredentials(() => invoke("save_connection", { connection: updatedConnection }));
     } catch (err) {
       console.error("Failed to rename connection", err);
       get().loadConnections();